@@ -0,0 +1,463 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::RwLock;
+use tokio::time;
+
+use crdt::Crdt;
+use maelstrom_node::{ids, protocol, Node};
+
+/// Configuration of the epidemic gossip.
+///
+/// Read from the environment, since maelstrom starts nodes without arguments:
+///  * `GOSSIP_FANOUT` is the number of peers to gossip with every round
+///  * `GOSSIP_INTERVAL_MS` is the time between rounds
+///  * `GOSSIP_SEED` seeds peer selection
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub fanout: usize,
+    pub interval: Duration,
+    pub seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            fanout: 3,
+            interval: Duration::from_millis(200),
+            seed: 0,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            fanout: env_or("GOSSIP_FANOUT", default.fanout),
+            interval: Duration::from_millis(env_or(
+                "GOSSIP_INTERVAL_MS",
+                default.interval.as_millis() as u64,
+            )),
+            seed: env_or("GOSSIP_SEED", default.seed),
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Random peer selection, reproducible for the same seed and node.
+pub struct Peers {
+    rng: StdRng,
+}
+
+impl Peers {
+    pub fn new(seed: u64, node_id: ids::NodeId) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed ^ u64::from(node_id)),
+        }
+    }
+
+    /// Picks up to fanout distinct nodes other than node_id.
+    pub fn pick(
+        &mut self,
+        node_id: ids::NodeId,
+        node_ids: &[ids::NodeId],
+        fanout: usize,
+    ) -> Vec<ids::NodeId> {
+        // sort to not depend on the order of membership updates
+        let mut candidates = node_ids
+            .iter()
+            .copied()
+            .filter(|id| *id != node_id)
+            .collect::<Vec<_>>();
+        candidates.sort();
+        candidates
+            .choose_multiple(&mut self.rng, fanout)
+            .copied()
+            .collect()
+    }
+}
+
+/// Tracks how many rounds it takes for the node to stop learning and
+/// spreading new messages.
+#[derive(Debug, Default)]
+pub struct Convergence {
+    round: u64,
+    unconverged_since: Option<u64>,
+    rounds: Vec<u64>,
+}
+
+impl Convergence {
+    pub fn round(&self) -> u64 {
+        self.round
+    }
+
+    /// Rounds it took to converge, one entry per time the node converged.
+    pub fn rounds(&self) -> &[u64] {
+        &self.rounds
+    }
+
+    /// Marks that the node has learned something new.
+    pub fn changed(&mut self) {
+        self.unconverged_since.get_or_insert(self.round);
+    }
+
+    /// Finishes a round. Quiet rounds are the ones where neither side of any
+    /// exchange has learned anything.
+    pub fn finish_round(&mut self, quiet: bool) {
+        self.round += 1;
+        if quiet {
+            if let Some(since) = self.unconverged_since.take() {
+                self.rounds.push(self.round - since);
+            }
+        }
+    }
+}
+
+/// State replicated by [DeltaGossip], together with what the node does with
+/// it.
+pub trait Replica: Clone + Send + Sync + 'static {
+    type State: Crdt + Clone + Default + Serialize + DeserializeOwned + Send + Sync + 'static;
+
+    /// Returns the part of the local state that is not in known.
+    fn delta(&self, known: &Self::State) -> impl Future<Output = Self::State> + Send;
+
+    /// Merges state received from a peer. Returns whether anything was new.
+    fn merge(&self, node: &Node, state: &Self::State) -> impl Future<Output = bool> + Send;
+
+    /// Called for every exchange the node starts.
+    fn exchanging(&self, _peer: ids::NodeId) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    /// Called at the end of every round. Quiet rounds are the ones where
+    /// neither side of any exchange has learned anything.
+    fn round_finished(&self, _quiet: bool) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+/// Request of a push-pull exchange, carries what the receiver is not known
+/// to have.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "gossip")]
+pub struct GossipRequest<T> {
+    pub delta: T,
+}
+
+#[derive(Deserialize)]
+struct GossipOkResponse<T> {
+    /// What the sender is not known to have.
+    delta: T,
+    /// Whether the receiver has learned anything from the request.
+    learned: bool,
+}
+
+/// Delta-state push-pull gossip: every round the node exchanges state with
+/// random peers. Only what the peer is not known to have is sent, so a round
+/// costs as much as there is new to spread, not as much as there is state.
+#[derive(Clone)]
+pub struct DeltaGossip<T> {
+    config: Config,
+    /// State every peer is known to have.
+    acked: Arc<RwLock<HashMap<ids::NodeId, T>>>,
+}
+
+impl<T> DeltaGossip<T>
+where
+    T: Crdt + Clone + Default + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            acked: Arc::default(),
+        }
+    }
+
+    /// Forgets what the peer is known to have, e.g. when it rejoins and may
+    /// have lost it.
+    pub async fn forget(&self, peer: ids::NodeId) {
+        self.acked.write().await.remove(&peer);
+    }
+
+    /// Runs gossip rounds forever.
+    pub async fn run<R: Replica<State = T>>(self, replica: R, node: Node) {
+        let mut peers = Peers::new(self.config.seed, node.id);
+        let mut interval = time::interval(self.config.interval);
+        loop {
+            interval.tick().await;
+
+            let picked = peers.pick(node.id, &node.node_ids(), self.config.fanout);
+            let mut deltas = Vec::with_capacity(picked.len());
+            for peer in picked {
+                let known = { self.acked.read().await.get(&peer).cloned() };
+                let delta = replica.delta(&known.unwrap_or_default()).await;
+                replica.exchanging(peer).await;
+                deltas.push((peer, delta));
+            }
+
+            // Sent even when there is nothing to push, to pull from the peer
+            let exchanges = deltas.into_iter().map(|(peer, delta)| {
+                let node = node.clone();
+                let timeout = self.config.interval;
+                async move {
+                    let request = node.send::<GossipOkResponse<T>>(
+                        peer.into(),
+                        GossipRequest {
+                            delta: delta.clone(),
+                        },
+                    );
+                    (peer, delta, time::timeout(timeout, request).await)
+                }
+            });
+
+            let mut quiet = true;
+            for (peer, delta, response) in futures::future::join_all(exchanges).await {
+                // Unreachable peers do not affect convergence, undelivered
+                // deltas are pushed again on one of the next rounds
+                let Ok(Ok(response)) = response else {
+                    continue;
+                };
+                {
+                    let mut acked = self.acked.write().await;
+                    let known = acked.entry(peer).or_default();
+                    known.merge(&delta);
+                    known.merge(&response.delta);
+                }
+                if response.learned {
+                    quiet = false;
+                }
+                if replica.merge(&node, &response.delta).await {
+                    quiet = false;
+                }
+            }
+
+            replica.round_finished(quiet).await;
+        }
+    }
+
+    /// Handles an exchange started by a peer: merges what the peer pushed,
+    /// and replies with everything the peer is not known to have.
+    pub async fn exchange<R: Replica<State = T>>(
+        &self,
+        replica: &R,
+        node: &Node,
+        message: &protocol::Message,
+        delta: T,
+    ) {
+        let learned = replica.merge(node, &delta).await;
+        let known = match message.source() {
+            ids::PeerId::Node(src_id) => {
+                let mut acked = self.acked.write().await;
+                let known = acked.entry(*src_id).or_default();
+                known.merge(&delta);
+                known.clone()
+            }
+            _ => delta,
+        };
+        let missing = replica.delta(&known).await;
+
+        node.reply(message, json!({"delta": missing, "learned": learned}))
+            .await
+            .expect("failed to send reply");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::range_set::RangeSet;
+
+    fn node_ids(count: u64) -> Vec<ids::NodeId> {
+        (0..count).map(ids::NodeId::from).collect()
+    }
+
+    #[test]
+    fn pick_is_reproducible() {
+        let node_ids = node_ids(25);
+        let mut a = Peers::new(42, 1.into());
+        let mut b = Peers::new(42, 1.into());
+        for _ in 0..10 {
+            let picked = a.pick(1.into(), &node_ids, 3);
+            assert_eq!(picked, b.pick(1.into(), &node_ids, 3));
+            assert_eq!(picked.len(), 3);
+            assert!(!picked.contains(&1.into()));
+        }
+    }
+
+    #[test]
+    fn pick_fanout_larger_than_cluster() {
+        let mut peers = Peers::new(0, 0.into());
+        let mut picked = peers.pick(0.into(), &node_ids(3), 10);
+        picked.sort();
+        assert_eq!(picked, [1.into(), 2.into()]);
+    }
+
+    #[test]
+    fn push_pull_converges() {
+        // every node starts with a single unique message, nodes exchange full
+        // sets with picked peers until everyone knows everything
+        let node_ids = node_ids(25);
+        let mut peers = node_ids
+            .iter()
+            .map(|id| Peers::new(7, *id))
+            .collect::<Vec<_>>();
+        let mut sets = node_ids
+            .iter()
+            .map(|id| [u64::from(*id)].into_iter().collect::<RangeSet>())
+            .collect::<Vec<_>>();
+        let all = node_ids
+            .iter()
+            .map(|id| u64::from(*id))
+            .collect::<RangeSet>();
+
+        let mut rounds = 0;
+        while sets.iter().any(|set| set != &all) {
+            rounds += 1;
+            assert!(rounds < 10, "did not converge");
+            for (i, node_id) in node_ids.iter().enumerate() {
+                for peer in peers[i].pick(*node_id, &node_ids, 3) {
+                    let j = u64::from(peer) as usize;
+                    let pushed = sets[i].clone();
+                    let pulled = sets[j].difference(&sets[i]);
+                    sets[j].union(&pushed);
+                    sets[i].union(&pulled);
+                }
+            }
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Set(Arc<RwLock<RangeSet>>);
+
+    impl Replica for Set {
+        type State = RangeSet;
+
+        async fn delta(&self, known: &RangeSet) -> RangeSet {
+            self.0.read().await.delta(known)
+        }
+
+        async fn merge(&self, _node: &Node, state: &RangeSet) -> bool {
+            let mut set = self.0.write().await;
+            let new = state.delta(&set);
+            set.merge(&new);
+            !new.is_empty()
+        }
+    }
+
+    #[derive(Clone)]
+    struct GossipHandler(Set, DeltaGossip<RangeSet>);
+
+    impl maelstrom_node::Handler for GossipHandler {
+        async fn handle(&self, node: Node, message: protocol::Message) {
+            let request = message
+                .clone_into::<protocol::Request<GossipRequest<RangeSet>>>()
+                .unwrap();
+            self.1
+                .exchange(&self.0, &node, &message, request.payload.delta)
+                .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn delta_gossip_converges() {
+        use tokio::sync::mpsc;
+
+        let config = Config {
+            fanout: 1,
+            interval: Duration::from_millis(10),
+            seed: 0,
+        };
+        let sets = [Set::default(), Set::default()];
+        sets[0].0.write().await.union(&[1, 2].into_iter().collect());
+        sets[1].0.write().await.insert(3);
+
+        let (outbox_tx, mut outbox_rx) = mpsc::channel::<protocol::Message>(100);
+        let mut inboxes = Vec::new();
+        for (i, set) in sets.iter().enumerate() {
+            let (inbox_tx, mut inbox_rx) = mpsc::channel(100);
+            let init = json!({
+                "src": "c0",
+                "dest": format!("n{i}"),
+                "body": {"type": "init", "msg_id": 0, "node_id": format!("n{i}"), "node_ids": ["n0", "n1"]},
+            });
+            inbox_tx
+                .send(serde_json::from_value(init).unwrap())
+                .await
+                .unwrap();
+            let node = Node::initialize(&mut inbox_rx, outbox_tx.clone()).await;
+            outbox_rx.recv().await.unwrap();
+
+            let gossip = DeltaGossip::new(config.clone());
+            tokio::spawn(gossip.clone().run(set.clone(), node.clone()));
+            let handler = GossipHandler(set.clone(), gossip);
+            tokio::spawn(async move { node.listen(&mut inbox_rx, handler).await });
+            inboxes.push(inbox_tx);
+        }
+
+        // routes messages between the nodes, and records pushed deltas
+        let pushed = Arc::new(std::sync::Mutex::new(Vec::<RangeSet>::new()));
+        let router = {
+            let pushed = pushed.clone();
+            async move {
+                while let Some(message) = outbox_rx.recv().await {
+                    let raw = serde_json::to_value(&message).unwrap();
+                    if raw["body"]["type"] == "gossip" {
+                        let delta = serde_json::from_value(raw["body"]["delta"].clone()).unwrap();
+                        pushed.lock().unwrap().push(delta);
+                    }
+                    let dest = raw["dest"].as_str().unwrap()[1..].parse::<usize>().unwrap();
+                    inboxes[dest].send(message).await.unwrap();
+                }
+            }
+        };
+        tokio::spawn(router);
+
+        let all = [1, 2, 3].into_iter().collect::<RangeSet>();
+        time::timeout(Duration::from_secs(1), async {
+            while *sets[0].0.read().await != all || *sets[1].0.read().await != all {
+                time::sleep(config.interval).await;
+            }
+        })
+        .await
+        .expect("did not converge");
+
+        // once everything is acknowledged, rounds push nothing
+        time::sleep(config.interval * 5).await;
+        pushed.lock().unwrap().clear();
+        time::sleep(config.interval * 5).await;
+        let pushed = pushed.lock().unwrap();
+        assert!(!pushed.is_empty());
+        assert!(pushed.iter().all(RangeSet::is_empty));
+    }
+
+    #[test]
+    fn convergence_rounds() {
+        let mut convergence = Convergence::default();
+        convergence.finish_round(true);
+        assert!(convergence.rounds().is_empty());
+
+        convergence.changed();
+        convergence.finish_round(false);
+        convergence.changed();
+        convergence.finish_round(false);
+        convergence.finish_round(true);
+
+        assert_eq!(convergence.round(), 4);
+        assert_eq!(convergence.rounds(), &[3]);
+    }
+}
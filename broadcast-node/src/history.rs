@@ -0,0 +1,70 @@
+use crate::range_set::RangeSet;
+
+/// Messages seen by the node, both as a set for deduplication and in the
+/// order they were received.
+#[derive(Debug, Default)]
+pub struct History {
+    seen: RangeSet,
+    log: Vec<u64>,
+}
+
+impl History {
+    /// Adds every message from messages, returns the ones that were not seen before.
+    pub fn merge(&mut self, messages: &RangeSet) -> Vec<u64> {
+        let new = messages.difference(&self.seen);
+        self.seen.union(&new);
+        let new = new.iter().collect::<Vec<_>>();
+        self.log.extend_from_slice(&new);
+        new
+    }
+
+    pub fn contains(&self, message: u64) -> bool {
+        self.seen.contains(message)
+    }
+
+    pub fn seen(&self) -> &RangeSet {
+        &self.seen
+    }
+
+    /// Cursor pointing right after the latest message.
+    pub fn cursor(&self) -> usize {
+        self.log.len()
+    }
+
+    /// Messages received after the cursor, in order of receipt.
+    pub fn since(&self, cursor: usize) -> &[u64] {
+        &self.log[cursor.min(self.log.len())..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_order() {
+        let mut history = History::default();
+        for message in [3, 1, 3, 2] {
+            history.merge(&[message].into_iter().collect());
+        }
+
+        assert_eq!(history.cursor(), 3);
+        assert_eq!(history.since(0), &[3, 1, 2]);
+        assert_eq!(history.since(2), &[2]);
+        assert!(history.since(3).is_empty());
+        assert!(history.since(10).is_empty());
+        assert_eq!(history.seen().ranges(), &[(1, 3)]);
+    }
+
+    #[test]
+    fn merge() {
+        let mut history = History::default();
+        history.merge(&[2].into_iter().collect());
+
+        let new = history.merge(&RangeSet::from(vec![(1, 4)]));
+
+        assert_eq!(new, [1, 3, 4]);
+        assert_eq!(history.since(0), &[2, 1, 3, 4]);
+        assert_eq!(history.seen().ranges(), &[(1, 4)]);
+    }
+}
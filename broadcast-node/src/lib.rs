@@ -0,0 +1,4 @@
+//! Parts of the broadcast node that are reusable by other set-like workloads.
+
+pub mod gossip;
+pub mod range_set;
@@ -1,147 +1,589 @@
+mod history;
+mod metrics;
 mod topology;
 
 use std::collections::HashMap;
-use std::collections::HashSet;
 use std::sync::Arc;
 
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::json;
+use tokio::signal;
 use tokio::sync::RwLock;
 use tokio::time;
-use tokio::time::Duration;
+use tokio::time::{Duration, Instant};
 use tokio::{spawn, sync};
 
+use broadcast_node::{gossip, range_set};
+use crdt::Crdt;
 use maelstrom_node::{ids, protocol, read_from_stdin, write_to_stdout, Handler, Node};
 
+/// How long shutdown waits for queued replies to be written.
+const SHUTDOWN_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
 #[derive(Default, Clone)]
 struct BroadcastHandler {
-    messages: Arc<RwLock<HashSet<u64>>>,
+    history: Arc<RwLock<history::History>>,
+    subscribers: Arc<RwLock<Vec<ids::PeerId>>>,
+    topology: Arc<RwLock<topology::Topology>>,
     broadcast_to: Arc<RwLock<Vec<ids::NodeId>>>,
+
+    /// When set, messages are spread by gossip instead of forwarding to neighbors.
+    gossip: Option<gossip::DeltaGossip<range_set::RangeSet>>,
+    convergence: Arc<RwLock<gossip::Convergence>>,
+
+    metrics: Arc<RwLock<metrics::Metrics>>,
 }
 
 #[derive(Deserialize)]
-#[serde(tag = "type", rename = "topology")]
-struct TopologyRequest {
-    topology: HashMap<ids::NodeId, Vec<ids::NodeId>>,
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    Topology {
+        topology: HashMap<ids::NodeId, Vec<ids::NodeId>>,
+    },
+    Broadcast {
+        message: u64,
+    },
+    Read {},
+    ReadSince {
+        cursor: usize,
+    },
+    Subscribe {
+        #[serde(default)]
+        cursor: usize,
+    },
+    Unsubscribe {},
+    Join {
+        node_id: ids::NodeId,
+        #[serde(default)]
+        neighbors: Vec<ids::NodeId>,
+    },
+    Leave {
+        node_id: ids::NodeId,
+    },
+    /// Sent to a new member, which knows nothing of the cluster yet.
+    Membership {
+        topology: HashMap<ids::NodeId, Vec<ids::NodeId>>,
+    },
+    Sync {
+        messages: range_set::RangeSet,
+    },
+    Gossip {
+        delta: range_set::RangeSet,
+    },
+    GossipStats {},
+    Stats {},
+}
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum MembershipRequest {
+    Join {
+        node_id: ids::NodeId,
+        neighbors: Vec<ids::NodeId>,
+    },
+    Leave {
+        node_id: ids::NodeId,
+    },
+    Membership {
+        topology: HashMap<ids::NodeId, Vec<ids::NodeId>>,
+    },
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize)]
+#[serde(tag = "type", rename = "sync")]
+struct SyncRequest {
+    messages: range_set::RangeSet,
+}
+
+#[derive(Clone, Serialize)]
 #[serde(tag = "type", rename = "broadcast")]
 struct BroadcastRequest {
     message: u64,
 }
 
 #[derive(Deserialize)]
-#[serde(tag = "type", rename = "broadcast_ok")]
-struct BroadcastOkResponse {}
+struct OkResponse {}
 
-#[derive(Deserialize)]
-#[serde(tag = "type", rename = "read")]
-struct ReadRequest {}
+#[derive(Serialize)]
+#[serde(tag = "type", rename = "messages")]
+struct MessagesNotification {
+    messages: Vec<u64>,
+    cursor: usize,
+}
 
-impl Handler for BroadcastHandler {
-    async fn handle(&self, node: maelstrom_node::Node, message: maelstrom_node::protocol::Message) {
-        if let Ok(request) = message.clone_into::<protocol::Request<TopologyRequest>>() {
-            let t = topology::Topology::from(&request.payload.topology);
-            {
-                *self.broadcast_to.write().await = t.next(node.id);
+impl BroadcastHandler {
+    fn new(gossip: Option<gossip::DeltaGossip<range_set::RangeSet>>) -> Self {
+        // Gossip does not wait for neighbors to acknowledge messages
+        let metrics = if gossip.is_some() {
+            metrics::Metrics::without_acks()
+        } else {
+            metrics::Metrics::default()
+        };
+        Self {
+            gossip,
+            metrics: Arc::new(RwLock::new(metrics)),
+            ..Default::default()
+        }
+    }
+
+    /// Remembers messages and notifies subscribers about the ones that were
+    /// not seen before. Returns the new messages.
+    async fn remember(&self, node: &Node, messages: &range_set::RangeSet) -> Vec<u64> {
+        let (new, cursor, subscribers) = {
+            let mut history = self.history.write().await;
+            let new = history.merge(messages);
+            // Subscribers are read under the history lock, so that every
+            // subscriber either got the message in the backlog or gets
+            // notified here
+            let subscribers = self.subscribers.read().await.clone();
+            (new, history.cursor(), subscribers)
+        };
+
+        if new.is_empty() {
+            return new;
+        }
+
+        self.convergence.write().await.changed();
+
+        for subscriber in subscribers {
+            node.notify(
+                subscriber,
+                MessagesNotification {
+                    messages: new.clone(),
+                    cursor,
+                },
+            )
+            .await
+            .expect("failed to notify");
+        }
+
+        new
+    }
+
+    async fn broadcast(&self, node: &Node, message: &protocol::Message, value: u64) {
+        let received_at = Instant::now();
+        let duplicate = self.history.read().await.contains(value);
+        {
+            let mut metrics = self.metrics.write().await;
+            if !matches!(message.source(), ids::PeerId::Node(_)) {
+                metrics.op();
             }
-            node.reply(&message, json!({}))
+            metrics.message(duplicate);
+        }
+
+        if duplicate {
+            node.reply(message, json!({}))
                 .await
-                .expect("failed to send reply")
-        } else if let Ok(request) = message.clone_into::<protocol::Request<BroadcastRequest>>() {
-            if self
-                .messages
+                .expect("failed to send reply");
+            return;
+        }
+
+        // Remember the message
+        let new = self.remember(node, &[value].into_iter().collect()).await;
+
+        node.reply(message, json!({}))
+            .await
+            .expect("failed to reply");
+
+        if new.is_empty() {
+            // Concurrent request with the same message got here first
+            return;
+        }
+
+        if self.gossip.is_some() {
+            // Message will be spread with the next gossip round
+            return;
+        }
+
+        let broadcast_to = if let ids::PeerId::Node(src_id) = message.source() {
+            self.broadcast_to
                 .read()
                 .await
-                .contains(&request.payload.message)
-            {
+                .clone()
+                .iter()
+                .copied()
+                .filter(|node_id|
+                    // Do not broadcast back to the sender
+                    !src_id.eq(node_id))
+                .collect()
+        } else {
+            self.broadcast_to.read().await.clone()
+        };
+
+        let broadcasts = broadcast_to.into_iter().map(|node_id| {
+            spawn(self.clone().send_with_retry(
+                node.clone(),
+                node_id,
+                BroadcastRequest { message: value },
+            ))
+        });
+
+        futures::future::join_all(broadcasts).await;
+
+        self.metrics.write().await.acked(received_at.elapsed());
+    }
+
+    /// Recomputes where to broadcast to, and sends everything known to the
+    /// neighbors that were not broadcasted to before.
+    async fn update_topology(&self, node: &Node, topology: &topology::Topology) {
+        let next = topology.next(node.id);
+        let added = {
+            let mut broadcast_to = self.broadcast_to.write().await;
+            let added = next
+                .iter()
+                .copied()
+                .filter(|node_id| !broadcast_to.contains(node_id))
+                .collect::<Vec<_>>();
+            *broadcast_to = next;
+            added
+        };
+        self.sync_to(node, added).await;
+    }
+
+    /// Transfers all known messages to the given nodes.
+    async fn sync_to(&self, node: &Node, node_ids: Vec<ids::NodeId>) {
+        let messages = { self.history.read().await.seen().clone() };
+        if messages.is_empty() {
+            return;
+        }
+        let syncs = node_ids.into_iter().map(|node_id| {
+            spawn(self.clone().send_with_retry(
+                node.clone(),
+                node_id,
+                SyncRequest {
+                    messages: messages.clone(),
+                },
+            ))
+        });
+        futures::future::join_all(syncs).await;
+    }
+
+    async fn join(
+        &self,
+        node: &Node,
+        message: &protocol::Message,
+        node_id: ids::NodeId,
+        neighbors: Vec<ids::NodeId>,
+    ) {
+        let neighbors = if neighbors.is_empty() {
+            // Unless told otherwise, new member is connected to the node it
+            // joined through
+            vec![node.id]
+        } else {
+            neighbors
+        };
+
+        let members = node.node_ids();
+        node.add_node(node_id);
+        // A rejoined node may have lost what it had
+        if let Some(gossip) = &self.gossip {
+            gossip.forget(node_id).await;
+        }
+        let topology = {
+            let mut topology = self.topology.write().await;
+            topology.add_node(node_id, &neighbors);
+            topology.clone()
+        };
+
+        node.reply(message, json!({}))
+            .await
+            .expect("failed to send reply");
+
+        if !matches!(message.source(), ids::PeerId::Node(_)) {
+            // Request came from outside the cluster, let other members know
+            let members = members
+                .into_iter()
+                .filter(|member| *member != node.id && *member != node_id);
+            for member in members {
+                spawn(self.clone().send_with_retry(
+                    node.clone(),
+                    member,
+                    MembershipRequest::Join {
+                        node_id,
+                        neighbors: neighbors.clone(),
+                    },
+                ));
+            }
+
+            // The new member learns the cluster, and then its messages
+            spawn(self.clone().send_with_retry(
+                node.clone(),
+                node_id,
+                MembershipRequest::Membership {
+                    topology: topology.edges().clone(),
+                },
+            ));
+            self.sync_to(node, vec![node_id]).await;
+        }
+
+        self.update_topology(node, &topology).await;
+    }
+
+    async fn leave(&self, node: &Node, message: &protocol::Message, node_id: ids::NodeId) {
+        node.remove_node(node_id);
+        if let Some(gossip) = &self.gossip {
+            gossip.forget(node_id).await;
+        }
+        let topology = {
+            let mut topology = self.topology.write().await;
+            topology.remove_node(node_id);
+            topology.clone()
+        };
+
+        node.reply(message, json!({}))
+            .await
+            .expect("failed to send reply");
+
+        if !matches!(message.source(), ids::PeerId::Node(_)) {
+            // Request came from outside the cluster, let other members know
+            let members = node
+                .node_ids()
+                .into_iter()
+                .filter(|member| *member != node.id);
+            for member in members {
+                spawn(self.clone().send_with_retry(
+                    node.clone(),
+                    member,
+                    MembershipRequest::Leave { node_id },
+                ));
+            }
+        }
+
+        self.update_topology(node, &topology).await;
+    }
+
+    /// Takes over membership and topology of the cluster this node joined.
+    async fn membership(
+        &self,
+        node: &Node,
+        message: &protocol::Message,
+        topology: HashMap<ids::NodeId, Vec<ids::NodeId>>,
+    ) {
+        for member in topology.keys() {
+            node.add_node(*member);
+        }
+        let topology = topology::Topology::from(&topology);
+        {
+            *self.topology.write().await = topology.clone();
+        }
+
+        node.reply(message, json!({}))
+            .await
+            .expect("failed to send reply");
+
+        self.update_topology(node, &topology).await;
+    }
+
+    async fn sync(&self, node: &Node, message: &protocol::Message, messages: range_set::RangeSet) {
+        self.remember(node, &messages).await;
+
+        node.reply(message, json!({}))
+            .await
+            .expect("failed to send reply");
+    }
+
+    /// Sends request to node until it is acknowledged, or the node leaves the cluster.
+    async fn send_with_retry(
+        self,
+        node: Node,
+        node_id: ids::NodeId,
+        request: impl Serialize + Clone,
+    ) {
+        let mut timeout_ms = 100;
+        while node.node_ids().contains(&node_id) {
+            self.metrics.write().await.sent(node_id);
+            let response = node.send::<OkResponse>(node_id.into(), request.clone());
+            let Ok(response) = time::timeout(Duration::from_millis(timeout_ms), response).await
+            else {
+                timeout_ms = (timeout_ms as f64 * 1.5) as u64;
+                continue;
+            };
+
+            if response.is_err() {
+                timeout_ms = (timeout_ms as f64 * 1.5) as u64;
+                continue;
+            }
+
+            break;
+        }
+    }
+}
+
+/// Messages spread by gossip, only in gossip mode.
+impl gossip::Replica for BroadcastHandler {
+    type State = range_set::RangeSet;
+
+    async fn delta(&self, known: &range_set::RangeSet) -> range_set::RangeSet {
+        self.history.read().await.seen().delta(known)
+    }
+
+    async fn merge(&self, node: &Node, messages: &range_set::RangeSet) -> bool {
+        !self.remember(node, messages).await.is_empty()
+    }
+
+    async fn exchanging(&self, peer: ids::NodeId) {
+        self.metrics.write().await.sent(peer);
+    }
+
+    async fn round_finished(&self, quiet: bool) {
+        self.convergence.write().await.finish_round(quiet);
+    }
+}
+
+impl Handler for BroadcastHandler {
+    async fn handle(&self, node: maelstrom_node::Node, message: maelstrom_node::protocol::Message) {
+        let Ok(request) = message.clone_into::<protocol::Request<Request>>() else {
+            // Ignore unknown requests
+            return;
+        };
+
+        if let ids::PeerId::Node(src_id) = message.source() {
+            self.metrics.write().await.received(*src_id);
+        }
+
+        match request.payload {
+            Request::Topology { topology } => {
+                let topology = topology::Topology::from(&topology);
+                {
+                    *self.topology.write().await = topology.clone();
+                }
                 node.reply(&message, json!({}))
                     .await
                     .expect("failed to send reply");
-            } else {
-                {
-                    // Remember the message
-                    self.messages.write().await.insert(request.payload.message);
-                }
-
+                self.update_topology(&node, &topology).await;
+            }
+            Request::Broadcast { message: value } => {
+                self.broadcast(&node, &message, value).await;
+            }
+            Request::Read {} => {
+                let messages = { self.history.read().await.seen().iter().collect::<Vec<_>>() };
+                node.reply(&message, json!({"messages": messages}))
+                    .await
+                    .expect("failed to send message");
+            }
+            Request::ReadSince { cursor } => {
+                let (messages, cursor) = {
+                    let history = self.history.read().await;
+                    (history.since(cursor).to_vec(), history.cursor())
+                };
+                node.reply(&message, json!({"messages": messages, "cursor": cursor}))
+                    .await
+                    .expect("failed to send message");
+            }
+            Request::Subscribe { cursor } => {
+                let (messages, cursor) = {
+                    let history = self.history.read().await;
+                    let mut subscribers = self.subscribers.write().await;
+                    if !subscribers.contains(message.source()) {
+                        subscribers.push(*message.source());
+                    }
+                    (history.since(cursor).to_vec(), history.cursor())
+                };
+                node.reply(&message, json!({"messages": messages, "cursor": cursor}))
+                    .await
+                    .expect("failed to send message");
+            }
+            Request::Unsubscribe {} => {
+                self.subscribers
+                    .write()
+                    .await
+                    .retain(|subscriber| subscriber != message.source());
                 node.reply(&message, json!({}))
                     .await
-                    .expect("failed to reply");
-
-                let broadcast_to = if let ids::PeerId::Node(src_id) = message.source() {
-                    self.broadcast_to
-                        .read()
-                        .await
-                        .clone()
-                        .iter()
-                        .copied()
-                        .filter(|node_id|
-                            // Do not broadcast back to the sender
-                            !src_id.eq(node_id))
-                        .collect()
-                } else {
-                    self.broadcast_to.read().await.clone()
+                    .expect("failed to send message");
+            }
+            Request::Join { node_id, neighbors } => {
+                self.join(&node, &message, node_id, neighbors).await;
+            }
+            Request::Leave { node_id } => {
+                self.leave(&node, &message, node_id).await;
+            }
+            Request::Membership { topology } => {
+                self.membership(&node, &message, topology).await;
+            }
+            Request::Sync { messages } => {
+                self.sync(&node, &message, messages).await;
+            }
+            Request::Gossip { delta } => {
+                if let Some(gossip) = &self.gossip {
+                    gossip.exchange(self, &node, &message, delta).await;
+                }
+            }
+            Request::GossipStats {} => {
+                let (round, rounds) = {
+                    let convergence = self.convergence.read().await;
+                    (convergence.round(), convergence.rounds().to_vec())
                 };
-
-                let broadcasts = broadcast_to.into_iter().map(|node_id| {
-                    spawn({
-                        let node = node.clone();
-                        async move {
-                            let mut timeout_ms = 100;
-                            loop {
-                                let response = node.send::<BroadcastOkResponse>(
-                                    node_id.into(),
-                                    BroadcastRequest {
-                                        message: request.payload.message,
-                                    },
-                                );
-                                let Ok(response) =
-                                    time::timeout(Duration::from_millis(timeout_ms), response)
-                                        .await
-                                else {
-                                    timeout_ms = (timeout_ms as f64 * 1.5) as u64;
-                                    continue;
-                                };
-
-                                if response.is_err() {
-                                    timeout_ms = (timeout_ms as f64 * 1.5) as u64;
-                                    continue;
-                                }
-
-                                break;
-                            }
-                        }
-                    })
-                });
-
-                futures::future::join_all(broadcasts).await;
-            }
-        } else if message
-            .clone_into::<protocol::Request<ReadRequest>>()
-            .is_ok()
-        {
-            let messages = { self.messages.read().await.clone() };
-            node.reply(&message, json!({"messages": messages}))
+                node.reply(
+                    &message,
+                    json!({"round": round, "rounds_to_convergence": rounds}),
+                )
                 .await
                 .expect("failed to send message");
-        } else {
-            // Ignore unknown requests
+            }
+            Request::Stats {} => {
+                let stats = { self.metrics.read().await.stats() };
+                node.reply(&message, stats)
+                    .await
+                    .expect("failed to send message");
+            }
         }
     }
 }
 
 #[tokio::main]
 async fn main() {
+    simplelog::TermLogger::init(
+        log::LevelFilter::Info,
+        simplelog::Config::default(),
+        simplelog::TerminalMode::Stderr,
+        simplelog::ColorChoice::Auto,
+    )
+    .expect("Logger init error");
+
     let mut requests_rx = read_from_stdin().await;
 
     let (responses_tx, responses_rx) = sync::mpsc::channel(100);
     let handle = spawn(write_to_stdout(responses_rx));
 
     let node = Node::initialize(&mut requests_rx, responses_tx.clone()).await;
-    node.listen(&mut requests_rx, BroadcastHandler::default())
-        .await;
+    // BROADCAST_MODE=gossip spreads messages by gossip instead of forwarding
+    let gossip = std::env::var("BROADCAST_MODE")
+        .is_ok_and(|mode| mode == "gossip")
+        .then(|| gossip::DeltaGossip::new(gossip::Config::from_env()));
+    let handler = BroadcastHandler::new(gossip);
+    let gossip = handler
+        .gossip
+        .clone()
+        .map(|gossip| spawn(gossip.run(handler.clone(), node.clone())));
+
+    let mut terminate =
+        signal::unix::signal(signal::unix::SignalKind::terminate()).expect("Signal handler error");
+    tokio::select! {
+        _ = node.listen(&mut requests_rx, handler.clone()) => {}
+        _ = signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+
+    // Dump stats on shutdown, so that they end up in node logs
+    let stats = { handler.metrics.read().await.stats() };
+    log::info!(
+        "stats: {}",
+        serde_json::to_string(&stats).expect("JSON serialize error")
+    );
 
-    handle.await.expect("Task panic");
+    // Gossip never ends on its own, the stdout writer ends once every reply
+    // queued so far is written. Retries to peers that are already gone hold
+    // the writer open, so it only gets a bounded time.
+    if let Some(gossip) = gossip {
+        gossip.abort();
+    }
+    drop(node);
+    drop(responses_tx);
+    match time::timeout(SHUTDOWN_FLUSH_TIMEOUT, handle).await {
+        Ok(result) => result.expect("Task panic"),
+        Err(_) => log::warn!("replies were not flushed before shutdown"),
+    }
+    std::process::exit(0);
 }
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::ids;
+
+/// Counters used to judge broadcast efficiency: messages per operation,
+/// duplicates and how long it takes to get a message acknowledged by every
+/// neighbor.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    peers: HashMap<ids::NodeId, PeerStats>,
+    ops: u64,
+    received: u64,
+    duplicates: u64,
+    ack_latencies: Vec<Duration>,
+    /// Acknowledgements are not awaited, so there is no ack latency to report.
+    without_acks: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct PeerStats {
+    pub sent: u64,
+    pub received: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub peers: HashMap<ids::NodeId, PeerStats>,
+    pub ops: u64,
+    pub sent: u64,
+    pub msgs_per_op: f64,
+    pub received: u64,
+    pub duplicates: u64,
+    pub duplicate_rate: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ack_latency_ms: Option<LatencyStats>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct LatencyStats {
+    pub count: usize,
+    pub median: u64,
+    pub max: u64,
+}
+
+impl Metrics {
+    /// Metrics of a node that does not wait for neighbors to acknowledge
+    /// messages, which leaves ack latency out of the stats.
+    pub fn without_acks() -> Self {
+        Self {
+            without_acks: true,
+            ..Self::default()
+        }
+    }
+
+    /// Records a broadcast request from a client.
+    pub fn op(&mut self) {
+        self.ops += 1;
+    }
+
+    pub fn sent(&mut self, node_id: ids::NodeId) {
+        self.peers.entry(node_id).or_default().sent += 1;
+    }
+
+    pub fn received(&mut self, node_id: ids::NodeId) {
+        self.peers.entry(node_id).or_default().received += 1;
+    }
+
+    /// Records a message received by the node, either from a client or a peer.
+    pub fn message(&mut self, duplicate: bool) {
+        self.received += 1;
+        if duplicate {
+            self.duplicates += 1;
+        }
+    }
+
+    /// Records time from the first receipt of a message until every neighbor
+    /// has acknowledged it.
+    pub fn acked(&mut self, latency: Duration) {
+        self.ack_latencies.push(latency);
+    }
+
+    pub fn stats(&self) -> Stats {
+        let sent = self.peers.values().map(|peer| peer.sent).sum::<u64>();
+        Stats {
+            peers: self.peers.clone(),
+            ops: self.ops,
+            sent,
+            msgs_per_op: ratio(sent, self.ops),
+            received: self.received,
+            duplicates: self.duplicates,
+            duplicate_rate: ratio(self.duplicates, self.received),
+            ack_latency_ms: (!self.without_acks).then(|| latency_stats(&self.ack_latencies)),
+        }
+    }
+}
+
+fn ratio(a: u64, b: u64) -> f64 {
+    if b == 0 {
+        0.0
+    } else {
+        a as f64 / b as f64
+    }
+}
+
+fn latency_stats(latencies: &[Duration]) -> LatencyStats {
+    let mut millis = latencies
+        .iter()
+        .map(|latency| latency.as_millis() as u64)
+        .collect::<Vec<_>>();
+    millis.sort();
+    LatencyStats {
+        count: millis.len(),
+        median: millis.get(millis.len() / 2).copied().unwrap_or_default(),
+        max: millis.last().copied().unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats() {
+        let mut metrics = Metrics::default();
+        metrics.op();
+        metrics.op();
+        metrics.sent(1.into());
+        metrics.sent(1.into());
+        metrics.sent(2.into());
+        metrics.received(2.into());
+        metrics.message(false);
+        metrics.message(false);
+        metrics.message(false);
+        metrics.message(true);
+        for ms in [30, 10, 20] {
+            metrics.acked(Duration::from_millis(ms));
+        }
+
+        let stats = metrics.stats();
+
+        assert_eq!(stats.sent, 3);
+        assert_eq!(stats.msgs_per_op, 1.5);
+        assert_eq!(stats.duplicate_rate, 0.25);
+        assert_eq!(stats.peers[&1.into()].sent, 2);
+        assert_eq!(stats.peers[&2.into()].received, 1);
+        assert_eq!(
+            stats.ack_latency_ms,
+            Some(LatencyStats {
+                count: 3,
+                median: 20,
+                max: 30,
+            })
+        );
+    }
+
+    #[test]
+    fn empty() {
+        let stats = Metrics::default().stats();
+        assert_eq!(stats.msgs_per_op, 0.0);
+        assert_eq!(stats.duplicate_rate, 0.0);
+        assert_eq!(stats.ack_latency_ms, Some(LatencyStats::default()));
+    }
+
+    #[test]
+    fn without_acks() {
+        let stats = Metrics::without_acks().stats();
+        assert_eq!(stats.ack_latency_ms, None);
+        assert!(!serde_json::to_value(&stats)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .contains_key("ack_latency_ms"));
+    }
+}
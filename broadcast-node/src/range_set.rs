@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+
+/// Set of integers stored as sorted runs of consecutive values.
+///
+/// Runs are inclusive `(start, end)` pairs that never overlap or touch, so a
+/// workload that broadcasts mostly sequential values is stored and sent as a
+/// handful of pairs instead of one entry per value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<(u64, u64)>", into = "Vec<(u64, u64)>")]
+pub struct RangeSet {
+    ranges: Vec<(u64, u64)>,
+}
+
+impl From<Vec<(u64, u64)>> for RangeSet {
+    fn from(ranges: Vec<(u64, u64)>) -> Self {
+        let mut set = Self::default();
+        for (start, end) in ranges {
+            set.insert_range(start.min(end), start.max(end));
+        }
+        set
+    }
+}
+
+impl From<RangeSet> for Vec<(u64, u64)> {
+    fn from(set: RangeSet) -> Self {
+        set.ranges
+    }
+}
+
+impl FromIterator<u64> for RangeSet {
+    fn from_iter<I: IntoIterator<Item = u64>>(iter: I) -> Self {
+        let mut set = Self::default();
+        for value in iter {
+            set.insert(value);
+        }
+        set
+    }
+}
+
+impl RangeSet {
+    /// Returns true if the value was not present before.
+    pub fn insert(&mut self, value: u64) -> bool {
+        if self.contains(value) {
+            false
+        } else {
+            self.insert_range(value, value);
+            true
+        }
+    }
+
+    pub fn contains(&self, value: u64) -> bool {
+        self.position(value).is_ok()
+    }
+
+    /// Number of values in the set, which does not fit in u64 for the full
+    /// range.
+    pub fn len(&self) -> u128 {
+        self.ranges
+            .iter()
+            .map(|(start, end)| u128::from(end - start) + 1)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn ranges(&self) -> &[(u64, u64)] {
+        &self.ranges
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        self.ranges.iter().flat_map(|&(start, end)| start..=end)
+    }
+
+    /// Adds every value of other to this set.
+    pub fn union(&mut self, other: &RangeSet) {
+        for &(start, end) in &other.ranges {
+            self.insert_range(start, end);
+        }
+    }
+
+    /// Returns values that are in this set, but not in other.
+    pub fn difference(&self, other: &RangeSet) -> RangeSet {
+        let mut ranges = vec![];
+        let mut theirs = other.ranges.iter().peekable();
+        for &(start, end) in &self.ranges {
+            // start of the part of the run that is not yet covered by other
+            let mut start = Some(start);
+            while let (Some(from), Some(&&(other_start, other_end))) = (start, theirs.peek()) {
+                if other_end < from {
+                    theirs.next();
+                    continue;
+                }
+                if other_start > end {
+                    break;
+                }
+                if other_start > from {
+                    ranges.push((from, other_start - 1));
+                }
+                if other_end >= end {
+                    start = None;
+                } else {
+                    start = Some(other_end + 1);
+                    theirs.next();
+                }
+            }
+            if let Some(from) = start {
+                ranges.push((from, end));
+            }
+        }
+        RangeSet { ranges }
+    }
+
+    /// Binary search for the run containing value. On miss, returns index
+    /// where a run starting at value would be inserted.
+    fn position(&self, value: u64) -> Result<usize, usize> {
+        self.ranges.binary_search_by(|&(start, end)| {
+            if end < value {
+                std::cmp::Ordering::Less
+            } else if start > value {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+    }
+
+    fn insert_range(&mut self, start: u64, end: u64) {
+        // first run that ends at or after start - 1, i.e. could touch the new one
+        let from = self
+            .ranges
+            .partition_point(|&(_, e)| e.saturating_add(1) < start);
+        // first run that starts after end + 1, i.e. is untouched by the new one
+        let to = self
+            .ranges
+            .partition_point(|&(s, _)| s <= end.saturating_add(1));
+
+        let (start, end) = self.ranges[from..to]
+            .iter()
+            .fold((start, end), |(start, end), &(s, e)| {
+                (start.min(s), end.max(e))
+            });
+        self.ranges.splice(from..to, [(start, end)]);
+    }
+}
+
+impl crdt::Crdt for RangeSet {
+    fn merge(&mut self, other: &Self) {
+        self.union(other);
+    }
+
+    fn delta(&self, other: &Self) -> Self {
+        self.difference(other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_merges_adjacent() {
+        let mut set = RangeSet::default();
+        assert!(set.insert(1));
+        assert!(set.insert(3));
+        assert_eq!(set.ranges(), &[(1, 1), (3, 3)]);
+        assert!(set.insert(2));
+        assert_eq!(set.ranges(), &[(1, 3)]);
+        assert!(!set.insert(2));
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn insert_bounds() {
+        let mut set = RangeSet::default();
+        set.insert(u64::MAX);
+        set.insert(0);
+        set.insert(u64::MAX - 1);
+        assert_eq!(set.ranges(), &[(0, 0), (u64::MAX - 1, u64::MAX)]);
+        assert_eq!(set.len(), 3);
+
+        let full = RangeSet::from(vec![(0, u64::MAX)]);
+        assert_eq!(full.len(), u128::from(u64::MAX) + 1);
+    }
+
+    #[test]
+    fn contains() {
+        let set = [1, 2, 3, 7, 8].into_iter().collect::<RangeSet>();
+        assert!(set.contains(1));
+        assert!(set.contains(3));
+        assert!(!set.contains(4));
+        assert!(!set.contains(6));
+        assert!(set.contains(8));
+        assert!(!set.contains(9));
+        assert!(!set.contains(0));
+    }
+
+    #[test]
+    fn union() {
+        let mut a = [1, 2, 10].into_iter().collect::<RangeSet>();
+        let b = [3, 4, 9, 20].into_iter().collect::<RangeSet>();
+        a.union(&b);
+        assert_eq!(a.ranges(), &[(1, 4), (9, 10), (20, 20)]);
+    }
+
+    #[test]
+    fn difference() {
+        let a = RangeSet::from(vec![(0, 10), (20, 30)]);
+        let b = RangeSet::from(vec![(2, 3), (5, 5), (9, 21), (30, 40)]);
+        assert_eq!(
+            a.difference(&b).ranges(),
+            &[(0, 1), (4, 4), (6, 8), (22, 29)]
+        );
+        assert!(b.difference(&b).is_empty());
+        assert_eq!(a.difference(&RangeSet::default()), a);
+    }
+
+    #[test]
+    fn iter() {
+        let set = [5, 1, 2, 3, 9].into_iter().collect::<RangeSet>();
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![1, 2, 3, 5, 9]);
+    }
+
+    #[test]
+    fn serde() {
+        let set = [1, 2, 3, 7].into_iter().collect::<RangeSet>();
+        let raw = serde_json::to_string(&set).expect("failed to serialize");
+        assert_eq!(raw, "[[1,3],[7,7]]");
+
+        let parsed =
+            serde_json::from_str::<RangeSet>("[[7,7],[2,1],[3,3]]").expect("failed to deserialize");
+        assert_eq!(parsed, set);
+    }
+}
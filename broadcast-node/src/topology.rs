@@ -2,7 +2,7 @@ use std::collections::{HashMap, HashSet};
 
 use crate::ids;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Topology(HashMap<ids::NodeId, Vec<ids::NodeId>>);
 
 impl From<&HashMap<ids::NodeId, Vec<ids::NodeId>>> for Topology {
@@ -85,6 +85,32 @@ impl Topology {
         }
     }
 
+    /// Adds a node connected both ways to every one of neighbors.
+    pub fn add_node(&mut self, node_id: ids::NodeId, neighbors: &[ids::NodeId]) {
+        for neighbor in neighbors.iter().filter(|id| **id != node_id) {
+            for (from, to) in [(node_id, *neighbor), (*neighbor, node_id)] {
+                let edges = self.0.entry(from).or_default();
+                if let Err(position) = edges.binary_search(&to) {
+                    edges.insert(position, to);
+                }
+            }
+        }
+        self.0.entry(node_id).or_default();
+    }
+
+    /// Removes a node and all edges leading to it.
+    pub fn remove_node(&mut self, node_id: ids::NodeId) {
+        self.0.remove(&node_id);
+        for neighbors in self.0.values_mut() {
+            neighbors.retain(|id| *id != node_id);
+        }
+    }
+
+    /// Neighbors of every node.
+    pub fn edges(&self) -> &HashMap<ids::NodeId, Vec<ids::NodeId>> {
+        &self.0
+    }
+
     fn get_neighbors(&self, node_id: &ids::NodeId) -> Vec<ids::NodeId> {
         self.0.get(node_id).cloned().unwrap_or_default()
     }
@@ -132,6 +158,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn add_node() {
+        /*
+         *   1↔2   →   1↔2
+         *             ↕
+         *             3
+         */
+        let mut topology = HashMap::<ids::NodeId, Vec<ids::NodeId>>::new();
+        topology.insert(1.into(), vec![2.into()]);
+        topology.insert(2.into(), vec![1.into()]);
+        let mut topology = Topology::from(&topology);
+
+        topology.add_node(3.into(), &[1.into()]);
+
+        assert_eq!(topology.next(1.into()), [3.into(), 2.into()]);
+        assert_eq!(topology.next(2.into()), [1.into()]);
+        assert_eq!(topology.next(3.into()), [1.into()]);
+    }
+
+    #[test]
+    fn remove_node() {
+        /*
+         *   1↔2↔3   →   1   3
+         */
+        let mut topology = HashMap::<ids::NodeId, Vec<ids::NodeId>>::new();
+        topology.insert(1.into(), vec![2.into()]);
+        topology.insert(2.into(), vec![1.into(), 3.into()]);
+        topology.insert(3.into(), vec![2.into()]);
+        let mut topology = Topology::from(&topology);
+
+        topology.remove_node(2.into());
+
+        assert!(topology.next(1.into()).is_empty());
+        assert!(topology.next(2.into()).is_empty());
+        assert!(topology.next(3.into()).is_empty());
+    }
+
     #[test]
     fn not_existing() {
         let topology = Topology::default();
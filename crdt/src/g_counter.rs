@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use maelstrom_node::ids;
+use serde::{Deserialize, Serialize};
+
+use crate::Crdt;
+
+/// Grow-only counter: every node increments only its own entry, replicas are
+/// merged by taking element-wise max.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct GCounter(HashMap<ids::NodeId, u64>);
+
+impl GCounter {
+    pub fn increment(&mut self, node_id: ids::NodeId, delta: u64) {
+        if delta > 0 {
+            *self.0.entry(node_id).or_default() += delta;
+        }
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0.values().sum()
+    }
+
+    /// Value contributed by a single node.
+    pub fn get(&self, node_id: ids::NodeId) -> u64 {
+        self.0.get(&node_id).copied().unwrap_or_default()
+    }
+}
+
+impl Crdt for GCounter {
+    fn merge(&mut self, other: &Self) {
+        for (node_id, count) in &other.0 {
+            if *count > self.get(*node_id) {
+                self.0.insert(*node_id, *count);
+            }
+        }
+    }
+
+    fn delta(&self, other: &Self) -> Self {
+        Self(
+            self.0
+                .iter()
+                .filter(|(node_id, count)| **count > other.get(**node_id))
+                .map(|(node_id, count)| (*node_id, *count))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::laws;
+
+    proptest! {
+        #[test]
+        fn laws(history in laws::history(0..10u64)) {
+            let replicas = laws::replay(history, GCounter::increment);
+            laws::check(&replicas);
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let mut a = GCounter::default();
+        a.increment(1.into(), 2);
+        a.increment(2.into(), 1);
+        let mut b = GCounter::default();
+        b.increment(2.into(), 5);
+
+        a.merge(&b);
+
+        assert_eq!(a.value(), 7);
+        assert_eq!(b.delta(&a), GCounter::default());
+    }
+
+    #[test]
+    fn serde() {
+        let mut counter = GCounter::default();
+        counter.increment(1.into(), 2);
+        let raw = serde_json::to_string(&counter).expect("failed to serialize");
+        assert_eq!(raw, r#"{"n1":2}"#);
+        assert_eq!(
+            serde_json::from_str::<GCounter>(&raw).expect("failed to deserialize"),
+            counter
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Crdt;
+
+/// Grow-only set: elements can be added, but never removed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent, bound(deserialize = "T: Eq + Hash + Deserialize<'de>"))]
+pub struct GSet<T: Eq + Hash>(HashSet<T>);
+
+impl<T: Eq + Hash> Default for GSet<T> {
+    fn default() -> Self {
+        Self(HashSet::new())
+    }
+}
+
+impl<T: Eq + Hash> GSet<T> {
+    /// Returns true if the value was not present before.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.0.insert(value)
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.0.contains(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.0.iter()
+    }
+}
+
+impl<T: Eq + Hash + Clone> Crdt for GSet<T> {
+    fn merge(&mut self, other: &Self) {
+        self.0.extend(other.0.iter().cloned());
+    }
+
+    fn delta(&self, other: &Self) -> Self {
+        Self(self.0.difference(&other.0).cloned().collect())
+    }
+}
+
+impl<T: Eq + Hash> FromIterator<T> for GSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::laws;
+
+    proptest! {
+        #[test]
+        fn laws(history in laws::history(0..20u64)) {
+            let replicas = laws::replay(history, |set: &mut GSet<u64>, _, value| {
+                set.insert(value);
+            });
+            laws::check(&replicas);
+        }
+    }
+
+    #[test]
+    fn delta() {
+        let a = [1, 2, 3].into_iter().collect::<GSet<_>>();
+        let b = [2, 4].into_iter().collect::<GSet<_>>();
+        assert_eq!(a.delta(&b), [1, 3].into_iter().collect());
+    }
+}
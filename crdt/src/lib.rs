@@ -0,0 +1,102 @@
+//! State-based CRDTs that can be exchanged between nodes.
+
+mod g_counter;
+mod g_set;
+mod lww_register;
+mod mv_register;
+mod or_set;
+mod pn_counter;
+mod two_p_set;
+
+pub use g_counter::GCounter;
+pub use g_set::GSet;
+pub use lww_register::LWWRegister;
+pub use mv_register::MVRegister;
+pub use or_set::ORSet;
+pub use pn_counter::PNCounter;
+pub use two_p_set::TwoPSet;
+
+pub trait Crdt {
+    /// Merges other replica into this one. Merge is commutative, associative
+    /// and idempotent, so replicas converge regardless of the order and number
+    /// of times they are merged.
+    fn merge(&mut self, other: &Self);
+
+    /// Returns the part of this replica that other has not seen yet, such that
+    /// merging the delta into other gives the same result as merging the
+    /// whole replica.
+    fn delta(&self, other: &Self) -> Self;
+}
+
+#[cfg(test)]
+mod laws {
+    use std::fmt::Debug;
+
+    use maelstrom_node::ids;
+    use proptest::prelude::*;
+
+    use super::Crdt;
+
+    pub const REPLICAS: usize = 3;
+
+    #[derive(Debug, Clone)]
+    pub enum Op<U> {
+        /// Local update made by the replica.
+        Update(U),
+        /// Merge of another replica into this one.
+        Merge(usize),
+    }
+
+    /// Random history of updates and merges between replicas.
+    pub fn history<U: Debug + Clone>(
+        update: impl Strategy<Value = U>,
+    ) -> impl Strategy<Value = Vec<(usize, Op<U>)>> {
+        let op = prop_oneof![
+            3 => update.prop_map(Op::Update),
+            1 => (0..REPLICAS).prop_map(Op::Merge),
+        ];
+        proptest::collection::vec((0..REPLICAS, op), 0..40)
+    }
+
+    /// Replays history, replica i makes updates as node i.
+    pub fn replay<T: Crdt + Clone + Default, U>(
+        history: Vec<(usize, Op<U>)>,
+        update: impl Fn(&mut T, ids::NodeId, U),
+    ) -> Vec<T> {
+        let mut replicas = vec![T::default(); REPLICAS];
+        for (i, op) in history {
+            match op {
+                Op::Update(u) => update(&mut replicas[i], (i as u64).into(), u),
+                Op::Merge(j) => {
+                    let other = replicas[j].clone();
+                    replicas[i].merge(&other);
+                }
+            }
+        }
+        replicas
+    }
+
+    pub fn merged<T: Crdt + Clone>(a: &T, b: &T) -> T {
+        let mut merged = a.clone();
+        merged.merge(b);
+        merged
+    }
+
+    /// Checks that merge of the replicas forms a semilattice, and that deltas
+    /// carry everything that is missing.
+    pub fn check<T: Crdt + Clone + PartialEq + Debug>(replicas: &[T]) {
+        let [a, b, c] = replicas else {
+            panic!("expected {REPLICAS} replicas");
+        };
+        // commutativity
+        assert_eq!(merged(a, b), merged(b, a));
+        // associativity
+        assert_eq!(merged(&merged(a, b), c), merged(a, &merged(b, c)));
+        // idempotence
+        assert_eq!(merged(a, a), *a);
+        assert_eq!(merged(&merged(a, b), b), merged(a, b));
+        // delta
+        assert_eq!(merged(b, &a.delta(b)), merged(b, a));
+        assert_eq!(merged(a, &a.delta(a)), *a);
+    }
+}
@@ -0,0 +1,95 @@
+use maelstrom_node::ids;
+use serde::{Deserialize, Serialize};
+
+use crate::Crdt;
+
+/// Last-writer-wins register. Writes are ordered by timestamp, ties are broken
+/// by id of the node that made the write.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LWWRegister<T> {
+    value: Option<T>,
+    stamp: Option<(u64, ids::NodeId)>,
+}
+
+impl<T> Default for LWWRegister<T> {
+    fn default() -> Self {
+        Self {
+            value: None,
+            stamp: None,
+        }
+    }
+}
+
+impl<T> LWWRegister<T> {
+    /// Returns false if the register already holds a later write.
+    pub fn set(&mut self, node_id: ids::NodeId, timestamp: u64, value: T) -> bool {
+        let stamp = Some((timestamp, node_id));
+        if stamp > self.stamp {
+            self.value = Some(value);
+            self.stamp = stamp;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+}
+
+impl<T: Clone> Crdt for LWWRegister<T> {
+    fn merge(&mut self, other: &Self) {
+        if other.stamp > self.stamp {
+            self.value = other.value.clone();
+            self.stamp = other.stamp;
+        }
+    }
+
+    fn delta(&self, other: &Self) -> Self {
+        if self.stamp > other.stamp {
+            self.clone()
+        } else {
+            Self::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::laws;
+
+    proptest! {
+        #[test]
+        fn laws(history in laws::history((0..5u64, 0..10u64))) {
+            let replicas = laws::replay(
+                history,
+                |register: &mut LWWRegister<u64>, node_id, (timestamp, value)| {
+                    register.set(node_id, timestamp, value);
+                },
+            );
+            laws::check(&replicas);
+        }
+    }
+
+    #[test]
+    fn later_write_wins() {
+        let mut a = LWWRegister::default();
+        let mut b = LWWRegister::default();
+        assert!(a.set(1.into(), 2, "a"));
+        assert!(b.set(2.into(), 1, "b"));
+        assert!(!a.set(1.into(), 1, "stale"));
+
+        b.merge(&a);
+        assert_eq!(b.get(), Some(&"a"));
+
+        // same timestamp, higher node id wins
+        let mut c = LWWRegister::default();
+        c.set(3.into(), 2, "c");
+        b.merge(&c);
+        assert_eq!(b.get(), Some(&"c"));
+    }
+}
@@ -0,0 +1,120 @@
+use maelstrom_node::ids;
+use serde::{Deserialize, Serialize};
+
+use crate::{Crdt, GCounter};
+
+/// Multi-value register: concurrent writes are all kept until a later write
+/// that has seen them replaces them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MVRegister<T> {
+    /// Concurrent values, each with the version vector of its write.
+    entries: Vec<(T, GCounter)>,
+}
+
+impl<T> Default for MVRegister<T> {
+    fn default() -> Self {
+        Self { entries: vec![] }
+    }
+}
+
+impl<T: PartialEq> PartialEq for MVRegister<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries.len() == other.entries.len()
+            && self
+                .entries
+                .iter()
+                .all(|entry| other.entries.contains(entry))
+    }
+}
+
+impl<T: Eq> Eq for MVRegister<T> {}
+
+/// Returns true if version a has seen everything b has, and something more.
+fn dominates(a: &GCounter, b: &GCounter) -> bool {
+    b.delta(a) == GCounter::default() && a.delta(b) != GCounter::default()
+}
+
+impl<T> MVRegister<T> {
+    /// Replaces all values this replica has seen.
+    pub fn set(&mut self, node_id: ids::NodeId, value: T) {
+        let mut version = GCounter::default();
+        for (_, seen) in &self.entries {
+            version.merge(seen);
+        }
+        version.increment(node_id, 1);
+        self.entries = vec![(value, version)];
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.entries.iter().map(|(value, _)| value)
+    }
+}
+
+impl<T: Clone> Crdt for MVRegister<T> {
+    fn merge(&mut self, other: &Self) {
+        let mut entries: Vec<(T, GCounter)> = vec![];
+        for (value, version) in self.entries.iter().chain(other.entries.iter()) {
+            let superseded = self
+                .entries
+                .iter()
+                .chain(other.entries.iter())
+                .any(|(_, other)| dominates(other, version));
+            let duplicate = entries.iter().any(|(_, other)| other == version);
+            if !superseded && !duplicate {
+                entries.push((value.clone(), version.clone()));
+            }
+        }
+        self.entries = entries;
+    }
+
+    fn delta(&self, other: &Self) -> Self {
+        Self {
+            entries: self
+                .entries
+                .iter()
+                .filter(|(_, version)| {
+                    !other
+                        .entries
+                        .iter()
+                        .any(|(_, other)| other == version || dominates(other, version))
+                })
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::laws;
+
+    proptest! {
+        #[test]
+        fn laws(history in laws::history(0..10u64)) {
+            let replicas = laws::replay(history, MVRegister::set);
+            laws::check(&replicas);
+        }
+    }
+
+    #[test]
+    fn concurrent_writes() {
+        let mut a = MVRegister::default();
+        a.set(1.into(), "a");
+        let mut b = a.clone();
+
+        a.set(1.into(), "b");
+        b.set(2.into(), "c");
+        a.merge(&b);
+
+        let mut values = a.values().copied().collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, ["b", "c"]);
+
+        a.set(1.into(), "d");
+        b.merge(&a);
+        assert_eq!(b.values().collect::<Vec<_>>(), [&"d"]);
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use maelstrom_node::ids;
+use serde::{Deserialize, Serialize};
+
+use crate::{Crdt, GCounter};
+
+/// Unique tag of a single add operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Dot {
+    node_id: ids::NodeId,
+    seq: u64,
+}
+
+/// Observed-remove set: remove only cancels the adds the replica has seen, so
+/// an element added concurrently with its removal stays in the set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "T: Eq + Hash + Deserialize<'de>"))]
+pub struct ORSet<T: Eq + Hash> {
+    /// Latest add sequence number of every node.
+    clock: GCounter,
+    adds: HashSet<(T, Dot)>,
+    removes: HashSet<Dot>,
+}
+
+impl<T: Eq + Hash> Default for ORSet<T> {
+    fn default() -> Self {
+        Self {
+            clock: GCounter::default(),
+            adds: HashSet::new(),
+            removes: HashSet::new(),
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone> ORSet<T> {
+    pub fn insert(&mut self, node_id: ids::NodeId, value: T) {
+        self.clock.increment(node_id, 1);
+        let dot = Dot {
+            node_id,
+            seq: self.clock.get(node_id),
+        };
+        self.adds.insert((value, dot));
+    }
+
+    /// Removes every observed add of the value.
+    pub fn remove(&mut self, value: &T) {
+        let dots = self
+            .adds
+            .iter()
+            .filter(|(v, _)| v == value)
+            .map(|(_, dot)| *dot)
+            .collect::<Vec<_>>();
+        self.removes.extend(dots);
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.adds
+            .iter()
+            .any(|(v, dot)| v == value && !self.removes.contains(dot))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        let mut seen = HashSet::new();
+        self.adds
+            .iter()
+            .filter(|(_, dot)| !self.removes.contains(dot))
+            .map(|(value, _)| value)
+            .filter(move |value| seen.insert(*value))
+    }
+}
+
+impl<T: Eq + Hash + Clone> Crdt for ORSet<T> {
+    fn merge(&mut self, other: &Self) {
+        self.clock.merge(&other.clock);
+        self.adds.extend(other.adds.iter().cloned());
+        self.removes.extend(other.removes.iter().copied());
+    }
+
+    fn delta(&self, other: &Self) -> Self {
+        Self {
+            clock: self.clock.delta(&other.clock),
+            adds: self.adds.difference(&other.adds).cloned().collect(),
+            removes: self.removes.difference(&other.removes).copied().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::laws;
+
+    proptest! {
+        #[test]
+        fn laws(history in laws::history((any::<bool>(), 0..5u64))) {
+            let replicas = laws::replay(history, |set: &mut ORSet<u64>, node_id, (add, value)| {
+                if add {
+                    set.insert(node_id, value);
+                } else {
+                    set.remove(&value);
+                }
+            });
+            laws::check(&replicas);
+        }
+    }
+
+    #[test]
+    fn concurrent_add_wins() {
+        let mut a = ORSet::default();
+        a.insert(1.into(), "x");
+        let mut b = a.clone();
+
+        a.remove(&"x");
+        b.insert(2.into(), "x");
+        a.merge(&b);
+
+        assert!(a.contains(&"x"));
+        assert_eq!(a.iter().collect::<Vec<_>>(), [&"x"]);
+    }
+
+    #[test]
+    fn re_add() {
+        let mut set = ORSet::default();
+        set.insert(1.into(), 1);
+        set.remove(&1);
+        assert!(!set.contains(&1));
+        set.insert(1.into(), 1);
+        assert!(set.contains(&1));
+    }
+}
@@ -0,0 +1,71 @@
+use maelstrom_node::ids;
+use serde::{Deserialize, Serialize};
+
+use crate::{Crdt, GCounter};
+
+/// Counter that supports decrements: increments and decrements are tracked in
+/// two separate grow-only counters.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PNCounter {
+    #[serde(rename = "p")]
+    increments: GCounter,
+    #[serde(rename = "n")]
+    decrements: GCounter,
+}
+
+impl PNCounter {
+    pub fn add(&mut self, node_id: ids::NodeId, delta: i64) {
+        if delta >= 0 {
+            self.increments.increment(node_id, delta.unsigned_abs());
+        } else {
+            self.decrements.increment(node_id, delta.unsigned_abs());
+        }
+    }
+
+    pub fn value(&self) -> i64 {
+        self.increments.value() as i64 - self.decrements.value() as i64
+    }
+}
+
+impl Crdt for PNCounter {
+    fn merge(&mut self, other: &Self) {
+        self.increments.merge(&other.increments);
+        self.decrements.merge(&other.decrements);
+    }
+
+    fn delta(&self, other: &Self) -> Self {
+        Self {
+            increments: self.increments.delta(&other.increments),
+            decrements: self.decrements.delta(&other.decrements),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::laws;
+
+    proptest! {
+        #[test]
+        fn laws(history in laws::history(-10..10i64)) {
+            let replicas = laws::replay(history, PNCounter::add);
+            laws::check(&replicas);
+        }
+    }
+
+    #[test]
+    fn value() {
+        let mut a = PNCounter::default();
+        a.add(1.into(), 5);
+        a.add(1.into(), -2);
+        let mut b = PNCounter::default();
+        b.add(2.into(), -4);
+
+        a.merge(&b);
+
+        assert_eq!(a.value(), -1);
+    }
+}
@@ -0,0 +1,99 @@
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Crdt, GSet};
+
+/// Two-phase set: an element can be added and then removed, but once removed
+/// it can never be added again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "T: Eq + Hash + Deserialize<'de>"))]
+pub struct TwoPSet<T: Eq + Hash> {
+    added: GSet<T>,
+    removed: GSet<T>,
+}
+
+impl<T: Eq + Hash> Default for TwoPSet<T> {
+    fn default() -> Self {
+        Self {
+            added: GSet::default(),
+            removed: GSet::default(),
+        }
+    }
+}
+
+impl<T: Eq + Hash + Clone> TwoPSet<T> {
+    /// Returns false if the value is already present, or has been removed.
+    pub fn insert(&mut self, value: T) -> bool {
+        if self.removed.contains(&value) {
+            false
+        } else {
+            self.added.insert(value)
+        }
+    }
+
+    /// Returns false if the value is not present.
+    pub fn remove(&mut self, value: &T) -> bool {
+        if self.contains(value) {
+            self.removed.insert(value.clone())
+        } else {
+            false
+        }
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.added.contains(value) && !self.removed.contains(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.added
+            .iter()
+            .filter(|value| !self.removed.contains(value))
+    }
+}
+
+impl<T: Eq + Hash + Clone> Crdt for TwoPSet<T> {
+    fn merge(&mut self, other: &Self) {
+        self.added.merge(&other.added);
+        self.removed.merge(&other.removed);
+    }
+
+    fn delta(&self, other: &Self) -> Self {
+        Self {
+            added: self.added.delta(&other.added),
+            removed: self.removed.delta(&other.removed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::laws;
+
+    proptest! {
+        #[test]
+        fn laws(history in laws::history((any::<bool>(), 0..10u64))) {
+            let replicas = laws::replay(history, |set: &mut TwoPSet<u64>, _, (add, value)| {
+                if add {
+                    set.insert(value);
+                } else {
+                    set.remove(&value);
+                }
+            });
+            laws::check(&replicas);
+        }
+    }
+
+    #[test]
+    fn remove_is_final() {
+        let mut set = TwoPSet::default();
+        assert!(set.insert(1));
+        assert!(set.remove(&1));
+        assert!(!set.insert(1));
+        assert!(!set.contains(&1));
+        assert!(!set.remove(&2));
+    }
+}
@@ -0,0 +1,50 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use maelstrom_node::ids;
+
+/// State-based counter CRDT that can be gossiped between nodes.
+pub trait Counter:
+    crdt::Crdt + Default + Clone + Serialize + DeserializeOwned + Send + Sync + 'static
+{
+    /// Adds delta to the entry of node_id. Fails if the counter can not
+    /// represent the change.
+    fn add(&mut self, node_id: ids::NodeId, delta: i64) -> Result<(), String>;
+
+    fn value(&self) -> i64;
+}
+
+impl Counter for crdt::GCounter {
+    fn add(&mut self, node_id: ids::NodeId, delta: i64) -> Result<(), String> {
+        let delta = u64::try_from(delta)
+            .map_err(|_| String::from("grow-only counter can not be decremented"))?;
+        self.increment(node_id, delta);
+        Ok(())
+    }
+
+    fn value(&self) -> i64 {
+        crdt::GCounter::value(self) as i64
+    }
+}
+
+impl Counter for crdt::PNCounter {
+    fn add(&mut self, node_id: ids::NodeId, delta: i64) -> Result<(), String> {
+        crdt::PNCounter::add(self, node_id, delta);
+        Ok(())
+    }
+
+    fn value(&self) -> i64 {
+        crdt::PNCounter::value(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn g_counter_rejects_decrement() {
+        let mut counter = crdt::GCounter::default();
+        assert!(Counter::add(&mut counter, 1.into(), -1).is_err());
+        assert_eq!(Counter::value(&counter), 0);
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::RwLock;
+use tokio::time::{self, Duration};
+
+use maelstrom_node::{protocol, ErrorCode, ErrorResponse, Handler, Node};
+
+use crate::counter::Counter;
+
+/// Keeps a per-node counter map and serves reads locally. Replicas converge by
+/// periodically exchanging their maps with every other node.
+#[derive(Clone)]
+pub struct GossipCounterHandler<C> {
+    counter: Arc<RwLock<C>>,
+    interval: Duration,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", bound = "C: DeserializeOwned")]
+enum Request<C> {
+    Add { delta: i64 },
+    Read {},
+    Merge { counter: C },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename = "merge")]
+struct MergeRequest<C> {
+    counter: C,
+}
+
+#[derive(Deserialize)]
+#[serde(bound = "C: DeserializeOwned")]
+struct MergeResponse<C> {
+    counter: C,
+}
+
+impl<C: Counter> GossipCounterHandler<C> {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            counter: Arc::new(RwLock::new(C::default())),
+            interval,
+        }
+    }
+
+    /// Every interval pushes the counter to all other nodes, and merges what
+    /// they reply with.
+    pub async fn gossip(self, node: Node) {
+        let mut interval = time::interval(self.interval);
+        loop {
+            interval.tick().await;
+
+            let counter = { self.counter.read().await.clone() };
+            let exchanges = node
+                .node_ids()
+                .into_iter()
+                .filter(|node_id| *node_id != node.id)
+                .map(|node_id| {
+                    let request = node.send::<MergeResponse<C>>(
+                        node_id.into(),
+                        MergeRequest {
+                            counter: counter.clone(),
+                        },
+                    );
+                    time::timeout(self.interval, request)
+                });
+
+            for response in futures::future::join_all(exchanges).await {
+                // Partitioned peers will catch up on one of the next rounds
+                let Ok(Ok(response)) = response else {
+                    continue;
+                };
+                self.counter.write().await.merge(&response.counter);
+            }
+        }
+    }
+}
+
+impl<C: Counter> Handler for GossipCounterHandler<C> {
+    async fn handle(&self, node: maelstrom_node::Node, message: maelstrom_node::protocol::Message) {
+        let Ok(request) = message.clone_into::<protocol::Request<Request<C>>>() else {
+            return;
+        };
+
+        match request.payload {
+            Request::Add { delta } => {
+                let added = { self.counter.write().await.add(node.id, delta) };
+                if let Err(text) = added {
+                    node.reply(
+                        &message,
+                        ErrorResponse {
+                            code: ErrorCode::MalformedRequest,
+                            text,
+                        },
+                    )
+                    .await
+                    .expect("failed to reply");
+                    return;
+                }
+                node.reply(&message, json!({}))
+                    .await
+                    .expect("failed to reply");
+            }
+            Request::Read {} => {
+                let value = { self.counter.read().await.value() };
+                node.reply(&message, json!({"value": value}))
+                    .await
+                    .expect("failed to respond");
+            }
+            Request::Merge { counter } => {
+                let merged = {
+                    let mut current = self.counter.write().await;
+                    current.merge(&counter);
+                    current.clone()
+                };
+                node.reply(&message, json!({"counter": merged}))
+                    .await
+                    .expect("failed to respond");
+            }
+        }
+    }
+}
@@ -1,61 +1,68 @@
+mod counter;
+mod gossip;
+
 use std::sync::{atomic, Arc};
 
 use serde::Deserialize;
 use serde_json::json;
+use tokio::time::Duration;
 use tokio::{spawn, sync};
 
+use kv::KeyValueStore;
 use maelstrom_node::{
-    ids, protocol, read_from_stdin, write_to_stdout, ErrorCode, Handler, Node, SendError,
+    ids, protocol, read_from_stdin, write_to_stdout, ErrorCode, ErrorResponse, Handler, Node,
 };
 
 #[derive(Clone)]
-struct GCounterHandler {
-    store: kv::KV,
+struct GCounterHandler<S> {
+    store: S,
 
-    counter: Arc<atomic::AtomicI64>,
     delta: Arc<atomic::AtomicI64>,
 }
 
-impl GCounterHandler {
-    pub fn new(store: kv::KV) -> Self {
+impl<S: KeyValueStore> GCounterHandler<S> {
+    pub fn new(store: S) -> Self {
         Self {
             store,
-            counter: Arc::new(atomic::AtomicI64::new(0)),
             delta: Arc::new(atomic::AtomicI64::new(0)),
         }
     }
 }
 
-impl GCounterHandler {
+impl<S: KeyValueStore> GCounterHandler<S> {
     async fn fetch_node_delta(
         &self,
         node: &maelstrom_node::Node,
         node_id: ids::NodeId,
-    ) -> Result<i64, SendError> {
+    ) -> Result<i64, kv::KvError> {
         if node_id == node.id {
             let delta = self.delta.fetch_add(0, atomic::Ordering::SeqCst);
             Ok(delta)
         } else {
-            // by writing a value before read, we force sequentially consistent store
-            // to return the latest value
-            self.store
-                .write(
-                    format!("{node_id}_seq"),
-                    self.counter.fetch_add(1, atomic::Ordering::SeqCst),
-                )
-                .await?;
-            match self.store.read::<i64>(node_id).await {
-                Ok(delta) => Ok(delta),
-                Err(SendError::Response(error)) if error.code == ErrorCode::KeyDoesNotExist => {
-                    Ok(0)
-                }
-                Err(error) => Err(error),
-            }
+            // reads are fresh, because store was synced before fetching
+            let delta = self.store.read_opt::<i64>(node_id).await?;
+            Ok(delta.unwrap_or_default())
         }
     }
+
+    async fn add(&self, node: &maelstrom_node::Node, delta: i64) -> Result<(), kv::KvError> {
+        let counter = self.delta.fetch_add(delta, atomic::Ordering::SeqCst);
+        self.store.write(node.id, counter + delta).await
+    }
+
+    async fn read(&self, node: &maelstrom_node::Node) -> Result<i64, kv::KvError> {
+        self.store.sync().await?;
+        let deltas = futures::future::try_join_all(
+            node.node_ids()
+                .iter()
+                .map(|node_id| self.fetch_node_delta(node, *node_id)),
+        )
+        .await?;
+        Ok(deltas.into_iter().sum())
+    }
 }
 
-impl Handler for GCounterHandler {
+impl<S: KeyValueStore> Handler for GCounterHandler<S> {
     async fn handle(&self, node: maelstrom_node::Node, message: maelstrom_node::protocol::Message) {
         #[derive(Deserialize)]
         #[serde(tag = "type", rename = "add")]
@@ -68,33 +75,29 @@ impl Handler for GCounterHandler {
         struct ReadRequest {}
 
         if let Ok(request) = message.clone_into::<protocol::Request<AddRequest>>() {
-            let counter = self
-                .delta
-                .fetch_add(request.payload.delta, atomic::Ordering::SeqCst);
-            self.store
-                .write(node.id, counter + request.payload.delta)
-                .await
-                .unwrap();
-            node.reply(&message, json!({}))
-                .await
-                .expect("failed to reply");
+            match self.add(&node, request.payload.delta).await {
+                Ok(()) => node.reply(&message, json!({})).await,
+                Err(error) => {
+                    // the delta is counted locally and written with the next
+                    // add, so the add may still take effect whatever the store
+                    // said
+                    let error = ErrorResponse {
+                        code: ErrorCode::Crash,
+                        text: format!("failed to write the counter: {error}"),
+                    };
+                    node.reply(&message, error).await
+                }
+            }
+            .expect("failed to reply");
         } else if message
             .clone_into::<protocol::Request<ReadRequest>>()
             .is_ok()
         {
-            let counter = futures::future::try_join_all(
-                node.node_ids
-                    .iter()
-                    .map(|node_id| self.fetch_node_delta(&node, *node_id)),
-            )
-            .await
-            .unwrap()
-            .into_iter()
-            .sum::<i64>();
-
-            node.reply(&message, json!({"value": counter}))
-                .await
-                .expect("failed to respond");
+            match self.read(&node).await {
+                Ok(counter) => node.reply(&message, json!({"value": counter})).await,
+                Err(error) => node.reply(&message, ErrorResponse::from(error)).await,
+            }
+            .expect("failed to respond");
         }
     }
 }
@@ -115,10 +118,141 @@ async fn main() {
     let handle = spawn(write_to_stdout(responses_rx));
 
     let node = Node::initialize(&mut requests_rx, responses_tx.clone()).await;
-    let store = kv::KV::new_seq(node.clone());
 
-    node.listen(&mut requests_rx, GCounterHandler::new(store))
-        .await;
+    let interval = std::env::var("GOSSIP_INTERVAL_MS")
+        .ok()
+        .and_then(|ms| ms.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::from_millis(200));
+
+    match std::env::var("G_COUNTER_MODE").as_deref() {
+        // Fallback that keeps per-node deltas in seq-kv
+        Ok("kv") => {
+            let store = kv::KV::new_seq(node.clone());
+            node.listen(&mut requests_rx, GCounterHandler::new(store))
+                .await;
+        }
+        // Maelstrom pn-counter workload, allows negative deltas
+        Ok("pn-counter") => {
+            let handler = gossip::GossipCounterHandler::<crdt::PNCounter>::new(interval);
+            spawn(handler.clone().gossip(node.clone()));
+            node.listen(&mut requests_rx, handler).await;
+        }
+        _ => {
+            let handler = gossip::GossipCounterHandler::<crdt::GCounter>::new(interval);
+            spawn(handler.clone().gossip(node.clone()));
+            node.listen(&mut requests_rx, handler).await;
+        }
+    }
 
     handle.await.expect("Task panic");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Requests sender and responses receiver of a node under test.
+    type Channels = (
+        sync::mpsc::Sender<protocol::Message>,
+        sync::mpsc::Receiver<protocol::Message>,
+    );
+
+    async fn start(node_id: u64, store: impl KeyValueStore) -> Channels {
+        let (requests_tx, mut requests_rx) = sync::mpsc::channel(10);
+        let (responses_tx, mut responses_rx) = sync::mpsc::channel(10);
+        requests_tx
+            .send(message(json!({
+                "type": "init",
+                "msg_id": 0,
+                "node_id": format!("n{node_id}"),
+                "node_ids": ["n0", "n1"],
+            })))
+            .await
+            .unwrap();
+        let node = Node::initialize(&mut requests_rx, responses_tx).await;
+        responses_rx.recv().await.unwrap();
+
+        spawn(async move {
+            node.listen(&mut requests_rx, GCounterHandler::new(store))
+                .await
+        });
+        (requests_tx, responses_rx)
+    }
+
+    fn message(body: serde_json::Value) -> protocol::Message {
+        serde_json::from_value(json!({"src": "c1", "dest": "n0", "body": body})).unwrap()
+    }
+
+    async fn request(node: &mut Channels, body: serde_json::Value) -> serde_json::Value {
+        node.0.send(message(body)).await.unwrap();
+        let response = node.1.recv().await.unwrap();
+        serde_json::to_value(response).unwrap()["body"].clone()
+    }
+
+    #[tokio::test]
+    async fn read_observes_other_nodes() {
+        let store = kv::MemoryKV::new(kv::Consistency::Sequential);
+        let mut n0 = start(0, store.client()).await;
+        let mut n1 = start(1, store.client()).await;
+
+        request(&mut n0, json!({"type": "add", "msg_id": 1, "delta": 2})).await;
+        // n0 has not observed this write yet
+        request(&mut n1, json!({"type": "add", "msg_id": 1, "delta": 5})).await;
+
+        let response = request(&mut n0, json!({"type": "read", "msg_id": 2})).await;
+        assert_eq!(response["value"], 7);
+    }
+
+    /// Store that rejects writes with a definite error.
+    #[derive(Clone)]
+    struct ReadOnly(kv::MemoryKV);
+
+    impl KeyValueStore for ReadOnly {
+        async fn sync(&self) -> Result<(), kv::KvError> {
+            self.0.sync().await
+        }
+
+        async fn read<R: serde::de::DeserializeOwned + Send>(
+            &self,
+            key: impl ToString + Send,
+        ) -> Result<R, kv::KvError> {
+            self.0.read(key).await
+        }
+
+        async fn write(
+            &self,
+            _key: impl ToString + Send,
+            _value: impl serde::Serialize + Send,
+        ) -> Result<(), kv::KvError> {
+            Err(kv::KvError::Unavailable(ErrorResponse {
+                code: ErrorCode::TemporarilyUnavailable,
+                text: String::from("read only"),
+            }))
+        }
+
+        async fn cas(
+            &self,
+            key: impl ToString + Send,
+            from: impl serde::Serialize + Send,
+            to: impl serde::Serialize + Send,
+            create_if_not_exists: bool,
+        ) -> Result<(), kv::KvError> {
+            self.0.cas(key, from, to, create_if_not_exists).await
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_add_is_indefinite() {
+        let store = kv::MemoryKV::new(kv::Consistency::Linearizable);
+        let mut n0 = start(0, ReadOnly(store)).await;
+
+        let response = request(&mut n0, json!({"type": "add", "msg_id": 1, "delta": 2})).await;
+        assert_eq!(response["type"], "error");
+        assert_eq!(response["code"], ErrorCode::Crash as u8);
+
+        // the delta is still counted
+        let response = request(&mut n0, json!({"type": "read", "msg_id": 2})).await;
+        assert_eq!(response["value"], 2);
+    }
+}
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::RwLock;
+use tokio::{spawn, sync};
+
+use broadcast_node::gossip;
+use broadcast_node::range_set::RangeSet;
+use crdt::Crdt;
+use maelstrom_node::{protocol, read_from_stdin, write_to_stdout, Handler, Node};
+
+/// Grow-only set, replicated with the delta-state gossip of the broadcast
+/// node: every round a node exchanges with random peers only the elements
+/// they are not known to have.
+#[derive(Clone)]
+struct GSetHandler {
+    elements: Arc<RwLock<RangeSet>>,
+    gossip: gossip::DeltaGossip<RangeSet>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    Add { element: u64 },
+    Read {},
+    Gossip { delta: RangeSet },
+}
+
+impl gossip::Replica for GSetHandler {
+    type State = RangeSet;
+
+    async fn delta(&self, known: &RangeSet) -> RangeSet {
+        self.elements.read().await.delta(known)
+    }
+
+    async fn merge(&self, _node: &Node, elements: &RangeSet) -> bool {
+        let mut current = self.elements.write().await;
+        let new = elements.delta(&current);
+        current.merge(&new);
+        !new.is_empty()
+    }
+}
+
+impl Handler for GSetHandler {
+    async fn handle(&self, node: maelstrom_node::Node, message: maelstrom_node::protocol::Message) {
+        let Ok(request) = message.clone_into::<protocol::Request<Request>>() else {
+            return;
+        };
+
+        match request.payload {
+            Request::Add { element } => {
+                self.elements.write().await.insert(element);
+                node.reply(&message, json!({}))
+                    .await
+                    .expect("failed to reply");
+            }
+            Request::Read {} => {
+                let value = { self.elements.read().await.iter().collect::<Vec<_>>() };
+                node.reply(&message, json!({"value": value}))
+                    .await
+                    .expect("failed to reply");
+            }
+            Request::Gossip { delta } => {
+                self.gossip.exchange(self, &node, &message, delta).await;
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut requests_rx = read_from_stdin().await;
+
+    let (responses_tx, responses_rx) = sync::mpsc::channel(100);
+    let handle = spawn(write_to_stdout(responses_rx));
+
+    let node = Node::initialize(&mut requests_rx, responses_tx.clone()).await;
+    let handler = GSetHandler {
+        elements: Arc::default(),
+        gossip: gossip::DeltaGossip::new(gossip::Config::from_env()),
+    };
+    spawn(handler.gossip.clone().run(handler.clone(), node.clone()));
+    node.listen(&mut requests_rx, handler).await;
+
+    handle.await.expect("Task panic");
+}
@@ -0,0 +1,697 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use maelstrom_node::storage::Snapshot;
+
+use crate::partition::{Partition, Retention, Stored};
+
+/// Append-only logs of messages by key, and offsets committed by consumers.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Logs {
+    logs: HashMap<String, Partition>,
+    /// Offsets committed without a consumer group.
+    #[serde(default)]
+    committed: HashMap<String, u32>,
+    /// Offsets committed by consumer groups.
+    #[serde(default)]
+    groups: HashMap<String, HashMap<String, u32>>,
+}
+
+/// Message with its metadata. Maelstrom sends bare integer messages, which
+/// have no metadata.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub msg: serde_json::Value,
+    /// Key that compaction keeps the latest record of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record_key: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Producer's timestamp, in milliseconds since unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+}
+
+impl Record {
+    /// Size counted by byte limits: the message as JSON and headers.
+    pub fn size(&self) -> usize {
+        let msg = serde_json::to_vec(&self.msg).map_or(0, |raw| raw.len());
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| name.len() + value.len())
+            .sum::<usize>();
+        msg + headers
+    }
+}
+
+/// Limits of a single poll, unset limits are unbounded.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Limits {
+    pub max_messages: Option<usize>,
+    pub max_bytes: Option<usize>,
+    pub max_messages_per_key: Option<usize>,
+    pub max_bytes_per_key: Option<usize>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Polled {
+    pub msgs: HashMap<String, Vec<Stored>>,
+    /// Offset to continue from, for every polled key that exists.
+    pub next_offsets: HashMap<String, usize>,
+}
+
+impl Polled {
+    /// Returns the poll reply. Messages are `[offset, msg]` pairs, as
+    /// maelstrom expects, or `[offset, msg, {headers, timestamp, ...}]` with
+    /// metadata.
+    pub fn into_reply(self, metadata: bool) -> serde_json::Value {
+        let msgs = self
+            .msgs
+            .into_iter()
+            .map(|(key, records)| {
+                let records = records
+                    .into_iter()
+                    .map(|stored| {
+                        let record = stored.record;
+                        if metadata {
+                            json!([
+                                stored.offset,
+                                record.msg,
+                                {
+                                    "record_key": record.record_key,
+                                    "headers": record.headers,
+                                    "timestamp": record.timestamp,
+                                    "append_time": stored.appended_at,
+                                },
+                            ])
+                        } else {
+                            json!([stored.offset, record.msg])
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                (key, records)
+            })
+            .collect::<HashMap<_, _>>();
+        json!({"msgs": msgs, "next_offsets": self.next_offsets})
+    }
+}
+
+/// Poll of an offset past the end of the log.
+#[derive(Debug, PartialEq)]
+pub struct OutOfRange {
+    pub key: String,
+    pub offset: u32,
+    pub next_offset: usize,
+}
+
+impl std::fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "offset {} of key {} is past the end of the log at {}",
+            self.offset, self.key, self.next_offset
+        )
+    }
+}
+
+/// Commit that would leave the committed offsets inconsistent.
+#[derive(Debug, PartialEq)]
+pub enum CommitError {
+    /// Offset of a message that does not exist yet.
+    PastEnd {
+        key: String,
+        offset: u32,
+        next_offset: usize,
+    },
+    /// Offset before the committed one, without force.
+    Rewind {
+        key: String,
+        offset: u32,
+        committed: u32,
+    },
+}
+
+impl std::fmt::Display for CommitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PastEnd {
+                key,
+                offset,
+                next_offset,
+            } => write!(
+                f,
+                "offset {offset} of key {key} is past the end of the log at {next_offset}"
+            ),
+            Self::Rewind {
+                key,
+                offset,
+                committed,
+            } => write!(
+                f,
+                "offset {offset} of key {key} is before the committed offset {committed}"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Entry {
+    Append {
+        key: String,
+        #[serde(flatten)]
+        record: Record,
+        #[serde(default)]
+        appended_at: u64,
+    },
+    /// Drops records of the key before the offset.
+    Truncate { key: String, before: usize },
+    /// Drops superseded records of the key.
+    Compact { key: String },
+    Commit {
+        group: Option<String>,
+        offsets: HashMap<String, u32>,
+    },
+}
+
+impl Logs {
+    /// Offset the next message appended to the key gets.
+    pub fn next_offset(&self, key: &str) -> usize {
+        self.logs.get(key).map_or(0, Partition::next_offset)
+    }
+
+    /// Returns the earliest offset of every key, which was appended at or
+    /// after the time, or None if there is no such offset.
+    pub fn offsets_for_times(
+        &self,
+        timestamps: &HashMap<String, u64>,
+    ) -> HashMap<String, Option<usize>> {
+        timestamps
+            .iter()
+            .map(|(key, timestamp)| {
+                let offset = self
+                    .logs
+                    .get(key)
+                    .and_then(|partition| partition.offset_for_time(*timestamp));
+                (key.clone(), offset)
+            })
+            .collect()
+    }
+
+    /// Returns the entries that bring every log within the retention policy.
+    pub fn retain(&self, retention: &Retention, now: u64) -> Vec<Entry> {
+        let mut entries = Vec::new();
+        for (key, partition) in &self.logs {
+            if retention.compact && partition.is_compactable() {
+                entries.push(Entry::Compact { key: key.clone() });
+            }
+            // checked against the compacted log, so applies after compaction
+            if let Some(before) = partition.expired_before(retention, now) {
+                entries.push(Entry::Truncate {
+                    key: key.clone(),
+                    before,
+                });
+            }
+        }
+        entries
+    }
+
+    /// Offsets committed by the group, or without a group if it is None.
+    pub fn committed(&self, group: Option<&str>) -> Option<&HashMap<String, u32>> {
+        match group {
+            Some(group) => self.groups.get(group),
+            None => Some(&self.committed),
+        }
+    }
+
+    /// Names of the groups that have committed offsets, in order.
+    pub fn groups(&self) -> Vec<&String> {
+        let mut groups = self.groups.keys().collect::<Vec<_>>();
+        groups.sort();
+        groups
+    }
+
+    /// Checks that the commit only moves committed offsets forward, unless
+    /// forced, and only to messages that exist.
+    pub fn check_commit(
+        &self,
+        group: Option<&str>,
+        offsets: &HashMap<String, u32>,
+        force: bool,
+    ) -> Result<(), CommitError> {
+        let mut offsets = offsets.iter().collect::<Vec<_>>();
+        offsets.sort();
+
+        let committed = self.committed(group);
+        for (key, offset) in offsets {
+            let next_offset = self.next_offset(key);
+            if *offset as usize >= next_offset {
+                return Err(CommitError::PastEnd {
+                    key: key.clone(),
+                    offset: *offset,
+                    next_offset,
+                });
+            }
+            match committed.and_then(|committed| committed.get(key)) {
+                Some(committed) if offset < committed && !force => {
+                    return Err(CommitError::Rewind {
+                        key: key.clone(),
+                        offset: *offset,
+                        committed: *committed,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns messages starting from the offset of every key, until any of
+    /// the limits is reached. Keys are polled in order, so the total limits
+    /// are spent on the first keys.
+    ///
+    /// Byte limits never stop the first message of the poll, or the poll
+    /// would not make progress on a message larger than the limit. The per key
+    /// byte limit never stops the first message of a key for the same reason.
+    ///
+    /// Polling the end of a log returns nothing, polling past it is an error.
+    /// Polling an offset dropped by retention or compaction returns from the
+    /// next retained one.
+    pub fn poll(
+        &self,
+        offsets: &HashMap<String, u32>,
+        limits: &Limits,
+    ) -> Result<Polled, OutOfRange> {
+        let mut offsets = offsets.iter().collect::<Vec<_>>();
+        offsets.sort();
+
+        for (key, offset) in &offsets {
+            let next_offset = self.next_offset(key);
+            if **offset as usize > next_offset {
+                return Err(OutOfRange {
+                    key: key.to_string(),
+                    offset: **offset,
+                    next_offset,
+                });
+            }
+        }
+
+        let mut polled = Polled::default();
+        let mut messages = 0;
+        let mut bytes = 0;
+        for (key, offset) in offsets {
+            let Some(partition) = self.logs.get(key) else {
+                continue;
+            };
+
+            let mut msgs = Vec::new();
+            let mut key_bytes = 0;
+            for stored in partition.since(*offset as usize) {
+                let size = stored.size;
+                let full = exceeds(limits.max_messages, messages + 1)
+                    || exceeds(limits.max_messages_per_key, msgs.len() + 1)
+                    || messages > 0 && exceeds(limits.max_bytes, bytes + size)
+                    || !msgs.is_empty() && exceeds(limits.max_bytes_per_key, key_bytes + size);
+                if full {
+                    break;
+                }
+                msgs.push(stored.clone());
+                messages += 1;
+                bytes += size;
+                key_bytes += size;
+            }
+
+            let next_offset = msgs
+                .last()
+                .map_or((*offset as usize).max(partition.base_offset()), |stored| {
+                    stored.offset + 1
+                });
+            polled.next_offsets.insert(key.clone(), next_offset);
+            polled.msgs.insert(key.clone(), msgs);
+        }
+        Ok(polled)
+    }
+}
+
+fn exceeds(limit: Option<usize>, value: usize) -> bool {
+    limit.is_some_and(|limit| value > limit)
+}
+
+impl Snapshot for Logs {
+    type Entry = Entry;
+
+    fn apply(&mut self, entry: Entry) {
+        match entry {
+            Entry::Append {
+                key,
+                record,
+                appended_at,
+            } => {
+                self.logs
+                    .entry(key)
+                    .or_default()
+                    .append(record, appended_at);
+            }
+            Entry::Truncate { key, before } => {
+                if let Some(partition) = self.logs.get_mut(&key) {
+                    partition.truncate(before);
+                }
+            }
+            Entry::Compact { key } => {
+                if let Some(partition) = self.logs.get_mut(&key) {
+                    partition.compact();
+                }
+            }
+            Entry::Commit { group, offsets } => {
+                let committed = match group {
+                    Some(group) => self.groups.entry(group).or_default(),
+                    None => &mut self.committed,
+                };
+                committed.extend(offsets);
+            }
+        }
+    }
+
+    fn snapshot(&self, writer: &mut dyn io::Write) -> io::Result<()> {
+        Ok(serde_json::to_writer(writer, self)?)
+    }
+
+    fn restore(reader: &mut dyn io::Read) -> io::Result<Self> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maelstrom_node::storage::{FsyncPolicy, Persistent};
+
+    use super::*;
+
+    fn append(key: &str, msg: u32) -> Entry {
+        Entry::Append {
+            key: key.to_string(),
+            record: Record {
+                msg: msg.into(),
+                ..Record::default()
+            },
+            appended_at: 0,
+        }
+    }
+
+    /// Polled offsets and integer messages of the key.
+    fn msgs(polled: &Polled, key: &str) -> Vec<(usize, u64)> {
+        polled.msgs[key]
+            .iter()
+            .map(|stored| (stored.offset, stored.record.msg.as_u64().unwrap()))
+            .collect()
+    }
+
+    fn logs(entries: impl IntoIterator<Item = Entry>) -> Logs {
+        let mut logs = Logs::default();
+        for entry in entries {
+            logs.apply(entry);
+        }
+        logs
+    }
+
+    #[test]
+    fn poll_limits() {
+        let logs = logs((0..5).flat_map(|i| [append("a", i), append("b", 100 + i)]));
+        let offsets = HashMap::from([("a".to_string(), 1), ("b".to_string(), 0)]);
+
+        let polled = logs.poll(&offsets, &Limits::default()).unwrap();
+        assert_eq!(msgs(&polled, "a"), [(1, 1), (2, 2), (3, 3), (4, 4)]);
+        assert_eq!(polled.next_offsets["b"], 5);
+
+        let limits = Limits {
+            max_messages: Some(5),
+            max_messages_per_key: Some(3),
+            ..Limits::default()
+        };
+        let polled = logs.poll(&offsets, &limits).unwrap();
+        assert_eq!(msgs(&polled, "a"), [(1, 1), (2, 2), (3, 3)]);
+        assert_eq!(msgs(&polled, "b"), [(0, 100), (1, 101)]);
+        assert_eq!(polled.next_offsets["a"], 4);
+        assert_eq!(polled.next_offsets["b"], 2);
+    }
+
+    #[test]
+    fn poll_byte_limits() {
+        let logs = logs([append("a", 1), append("a", 2), append("b", 100)]);
+        let offsets = HashMap::from([("a".to_string(), 0), ("b".to_string(), 0)]);
+
+        let limits = Limits {
+            max_bytes: Some(2),
+            ..Limits::default()
+        };
+        let polled = logs.poll(&offsets, &limits).unwrap();
+        assert_eq!(msgs(&polled, "a"), [(0, 1), (1, 2)]);
+        assert!(msgs(&polled, "b").is_empty());
+        assert_eq!(polled.next_offsets["b"], 0);
+
+        // the first message of the poll is returned even if it does not fit
+        let limits = Limits {
+            max_bytes: Some(1),
+            ..Limits::default()
+        };
+        let b = HashMap::from([("b".to_string(), 0)]);
+        let polled = logs.poll(&b, &limits).unwrap();
+        assert_eq!(msgs(&polled, "b"), [(0, 100)]);
+
+        // and so is the first message of a key
+        let limits = Limits {
+            max_bytes_per_key: Some(1),
+            ..Limits::default()
+        };
+        let polled = logs.poll(&offsets, &limits).unwrap();
+        assert_eq!(msgs(&polled, "a"), [(0, 1)]);
+        assert_eq!(msgs(&polled, "b"), [(0, 100)]);
+        assert_eq!(polled.next_offsets["a"], 1);
+    }
+
+    #[test]
+    fn poll_total_bytes_across_keys() {
+        let logs = logs((0..4).map(|i| append(&format!("k{i}"), 10 + i)));
+        let offsets = (0..4).map(|i| (format!("k{i}"), 0)).collect();
+
+        // every message is 2 bytes, so only two keys fit
+        let limits = Limits {
+            max_bytes: Some(5),
+            ..Limits::default()
+        };
+        let polled = logs.poll(&offsets, &limits).unwrap();
+        let returned = polled.msgs.values().map(Vec::len).sum::<usize>();
+        assert_eq!(returned, 2);
+        assert_eq!(msgs(&polled, "k0"), [(0, 10)]);
+        assert_eq!(msgs(&polled, "k1"), [(0, 11)]);
+        assert_eq!(polled.next_offsets["k2"], 0);
+    }
+
+    #[test]
+    fn reply_shape() {
+        let record = Record {
+            msg: json!({"text": "hello"}),
+            record_key: Some("user".to_string()),
+            headers: BTreeMap::from([("trace".to_string(), "abc".to_string())]),
+            timestamp: Some(5),
+        };
+        let logs = logs([
+            append("a", 1),
+            Entry::Append {
+                key: "a".to_string(),
+                record,
+                appended_at: 7,
+            },
+        ]);
+        let offsets = HashMap::from([("a".to_string(), 0)]);
+
+        let reply = logs
+            .poll(&offsets, &Limits::default())
+            .unwrap()
+            .into_reply(false);
+        assert_eq!(
+            reply,
+            json!({
+                "msgs": {"a": [[0, 1], [1, {"text": "hello"}]]},
+                "next_offsets": {"a": 2},
+            })
+        );
+
+        let reply = logs
+            .poll(&offsets, &Limits::default())
+            .unwrap()
+            .into_reply(true);
+        assert_eq!(
+            reply["msgs"]["a"],
+            json!([
+                [0, 1, {"record_key": null, "headers": {}, "timestamp": null, "append_time": 0}],
+                [
+                    1,
+                    {"text": "hello"},
+                    {
+                        "record_key": "user",
+                        "headers": {"trace": "abc"},
+                        "timestamp": 5,
+                        "append_time": 7,
+                    },
+                ],
+            ])
+        );
+    }
+
+    #[test]
+    fn record_size() {
+        let record = Record {
+            msg: json!("hi"),
+            headers: BTreeMap::from([("k".to_string(), "vv".to_string())]),
+            ..Record::default()
+        };
+        // quoted message and header name and value
+        assert_eq!(record.size(), 4 + 3);
+    }
+
+    #[test]
+    fn poll_out_of_range() {
+        let logs = logs([append("a", 1)]);
+
+        let end = HashMap::from([("a".to_string(), 1)]);
+        let polled = logs.poll(&end, &Limits::default()).unwrap();
+        assert!(polled.msgs["a"].is_empty());
+        assert_eq!(polled.next_offsets["a"], 1);
+
+        let past_end = HashMap::from([("a".to_string(), 2)]);
+        assert_eq!(
+            logs.poll(&past_end, &Limits::default()),
+            Err(OutOfRange {
+                key: "a".to_string(),
+                offset: 2,
+                next_offset: 1,
+            })
+        );
+
+        let missing = HashMap::from([("b".to_string(), 0)]);
+        let polled = logs.poll(&missing, &Limits::default()).unwrap();
+        assert!(polled.msgs.is_empty());
+    }
+
+    fn commit(group: Option<&str>, offsets: &[(&str, u32)]) -> Entry {
+        Entry::Commit {
+            group: group.map(String::from),
+            offsets: offsets
+                .iter()
+                .map(|(key, offset)| (key.to_string(), *offset))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn commits_per_group() {
+        let logs = logs([
+            append("a", 1),
+            append("a", 2),
+            commit(None, &[("a", 0)]),
+            commit(Some("g"), &[("a", 1)]),
+        ]);
+
+        assert_eq!(logs.committed(None).unwrap()["a"], 0);
+        assert_eq!(logs.committed(Some("g")).unwrap()["a"], 1);
+        assert_eq!(logs.committed(Some("other")), None);
+        assert_eq!(logs.groups(), ["g"]);
+    }
+
+    #[test]
+    fn commits_are_monotonic() {
+        let logs = logs([append("a", 1), append("a", 2), commit(None, &[("a", 1)])]);
+        let offsets = |offset| HashMap::from([("a".to_string(), offset)]);
+
+        assert_eq!(
+            logs.check_commit(None, &offsets(0), false),
+            Err(CommitError::Rewind {
+                key: "a".to_string(),
+                offset: 0,
+                committed: 1,
+            })
+        );
+        assert_eq!(logs.check_commit(None, &offsets(0), true), Ok(()));
+        assert_eq!(logs.check_commit(Some("g"), &offsets(0), false), Ok(()));
+        assert_eq!(
+            logs.check_commit(None, &offsets(2), true),
+            Err(CommitError::PastEnd {
+                key: "a".to_string(),
+                offset: 2,
+                next_offset: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn poll_truncated() {
+        let mut logs = logs((0..4).map(|i| append("a", i)));
+        let retention = Retention {
+            max_bytes: Some(2),
+            ..Retention::default()
+        };
+        for entry in logs.retain(&retention, 0) {
+            logs.apply(entry);
+        }
+        assert!(logs.retain(&retention, 0).is_empty());
+
+        let offsets = HashMap::from([("a".to_string(), 1)]);
+        let polled = logs.poll(&offsets, &Limits::default()).unwrap();
+        assert_eq!(msgs(&polled, "a"), [(2, 2), (3, 3)]);
+        assert_eq!(polled.next_offsets["a"], 4);
+    }
+
+    #[test]
+    fn retain_compacts_and_truncates() {
+        let mut logs = logs((0..4).map(|i| Entry::Append {
+            key: "a".to_string(),
+            record: Record {
+                msg: i.into(),
+                record_key: Some(format!("k{}", i % 2)),
+                ..Record::default()
+            },
+            appended_at: 100 * i,
+        }));
+        let retention = Retention {
+            max_age: Some(std::time::Duration::from_millis(150)),
+            compact: true,
+            ..Retention::default()
+        };
+
+        let entries = logs.retain(&retention, 400);
+        assert!(matches!(
+            entries[..],
+            [Entry::Compact { .. }, Entry::Truncate { before: 3, .. }]
+        ));
+        for entry in entries {
+            logs.apply(entry);
+        }
+        assert!(logs.retain(&retention, 400).is_empty());
+
+        let offsets = HashMap::from([("a".to_string(), 0)]);
+        let polled = logs.poll(&offsets, &Limits::default()).unwrap();
+        assert_eq!(msgs(&polled, "a"), [(3, 3)]);
+    }
+
+    #[test]
+    fn survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut logs = Persistent::<Logs>::open(dir.path(), FsyncPolicy::Never).unwrap();
+        logs.apply(append("a", 1)).unwrap();
+        logs.apply(append("b", 2)).unwrap();
+        logs.snapshot().unwrap();
+        logs.apply(append("a", 3)).unwrap();
+        drop(logs);
+
+        let logs = Persistent::<Logs>::open(dir.path(), FsyncPolicy::Never).unwrap();
+        assert_eq!(logs.state().next_offset("a"), 2);
+        assert_eq!(logs.state().logs["a"].since(1)[0].record.msg, 3);
+        assert_eq!(logs.state().logs["b"].since(0)[0].record.msg, 2);
+    }
+}
@@ -1,4 +1,8 @@
+mod logs;
+mod partition;
+
 use std::collections::HashSet;
+use std::time::Duration;
 use std::{collections::HashMap, sync::Arc};
 
 use serde::{Deserialize, Serialize};
@@ -6,76 +10,208 @@ use serde_json::json;
 use tokio::sync::RwLock;
 use tokio::{spawn, sync};
 
-use maelstrom_node::{protocol, read_from_stdin, write_to_stdout, Handler, Node};
+use maelstrom_node::storage::{self, FsyncPolicy, Persistent};
+use maelstrom_node::{
+    protocol, read_from_stdin, write_to_stdout, ErrorCode, ErrorResponse, Handler, Node,
+};
+
+use logs::Logs;
 
-#[derive(Clone, Default)]
+#[derive(Clone)]
 struct KafkaHandler {
-    logs: Arc<RwLock<HashMap<String, Vec<u32>>>>,
+    logs: Arc<RwLock<Persistent<Logs>>>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 enum Request {
-    Send { key: String, msg: u32 },
-    Poll { offsets: HashMap<String, u32> },
-    CommitOffsets { offsets: HashMap<String, u32> },
-    ListCommittedOffsets { keys: HashSet<String> },
+    Send {
+        key: String,
+        #[serde(flatten)]
+        record: logs::Record,
+    },
+    Poll {
+        offsets: HashMap<String, u32>,
+        #[serde(flatten)]
+        limits: logs::Limits,
+        /// Returns headers and timestamps along with messages.
+        #[serde(default)]
+        metadata: bool,
+    },
+    CommitOffsets {
+        offsets: HashMap<String, u32>,
+        #[serde(default)]
+        group: Option<String>,
+        /// Allows moving committed offsets back.
+        #[serde(default)]
+        force: bool,
+    },
+    ListCommittedOffsets {
+        keys: HashSet<String>,
+        #[serde(default)]
+        group: Option<String>,
+    },
+    ListGroups,
+    /// Looks up the earliest offset of every key appended at or after the
+    /// timestamp, in milliseconds since unix epoch.
+    OffsetsForTimes {
+        timestamps: HashMap<String, u64>,
+    },
 }
 
+/// Types of [Request], messages of other types are not supported.
+const REQUEST_TYPES: &[&str] = &[
+    "send",
+    "poll",
+    "commit_offsets",
+    "list_committed_offsets",
+    "list_groups",
+    "offsets_for_times",
+];
+
 impl Handler for KafkaHandler {
     async fn handle(&self, node: maelstrom_node::Node, message: maelstrom_node::protocol::Message) {
-        let Ok(request) = message.clone_into::<protocol::Request<Request>>() else {
+        // checked first, so malformed requests of known types are told apart
+        if let Some(kind) = message
+            .message_type()
+            .filter(|kind| !REQUEST_TYPES.contains(kind))
+        {
+            let error = ErrorResponse {
+                code: ErrorCode::NotSupported,
+                text: format!("request type {kind} is not supported"),
+            };
+            let _ = node.reply(&message, error).await;
             return;
+        }
+
+        let request = match message.clone_into::<protocol::Request<Request>>() {
+            Ok(request) => request,
+            Err(error) => {
+                let error = ErrorResponse {
+                    code: ErrorCode::MalformedRequest,
+                    text: error.to_string(),
+                };
+                // messages without msg_id can't be replied to
+                let _ = node.reply(&message, error).await;
+                return;
+            }
         };
 
         match request.payload {
-            Request::Send { key, msg } => {
+            Request::Send { key, .. } if key.is_empty() => {
+                let error = ErrorResponse {
+                    code: ErrorCode::MalformedRequest,
+                    text: String::from("key must not be empty"),
+                };
+                node.reply(&message, error)
+                    .await
+                    .expect("failed to send reply");
+            }
+            Request::Send { key, record } => {
                 let mut logs = self.logs.write().await;
-                let log = logs.entry(key).or_default();
-                let offset = log.len();
-                log.push(msg);
+                let offset = logs.state().next_offset(&key);
+                let synced = logs
+                    .apply_deferred(logs::Entry::Append {
+                        key,
+                        record,
+                        appended_at: partition::now_ms(),
+                    })
+                    .expect("failed to persist message");
+                // other requests need not wait for the flush
+                drop(logs);
+                synced.wait().await.expect("failed to persist message");
                 node.reply(&message, json!({"offset": offset}))
                     .await
                     .expect("failed to send reply");
             }
-            Request::Poll { offsets } => {
-                let logs = { self.logs.read().await.clone() };
+            Request::Poll {
+                offsets,
+                limits,
+                metadata,
+            } => {
+                let polled = self.logs.read().await.state().poll(&offsets, &limits);
+
+                match polled {
+                    Ok(polled) => node.reply(&message, polled.into_reply(metadata)).await,
+                    Err(error) => {
+                        let error = ErrorResponse {
+                            code: ErrorCode::PreconditionFailed,
+                            text: error.to_string(),
+                        };
+                        node.reply(&message, error).await
+                    }
+                }
+                .expect("failed to send reply");
+            }
+            Request::CommitOffsets {
+                offsets,
+                group,
+                force,
+            } => {
+                let mut logs = self.logs.write().await;
+                let checked = logs.state().check_commit(group.as_deref(), &offsets, force);
+                let synced = checked.is_ok().then(|| {
+                    logs.apply_deferred(logs::Entry::Commit { group, offsets })
+                        .expect("failed to persist commit")
+                });
+                drop(logs);
+                if let Some(synced) = synced {
+                    synced.wait().await.expect("failed to persist commit");
+                }
+
+                match checked {
+                    Ok(()) => node.reply(&message, json!({})).await,
+                    Err(error) => {
+                        let error = ErrorResponse {
+                            code: ErrorCode::PreconditionFailed,
+                            text: error.to_string(),
+                        };
+                        node.reply(&message, error).await
+                    }
+                }
+                .expect("failed to send reply");
+            }
+            Request::ListCommittedOffsets { keys, group } => {
+                let logs = self.logs.read().await;
 
-                let msgs = logs
+                let offsets = logs
+                    .state()
+                    .committed(group.as_deref())
                     .into_iter()
-                    .filter_map(|(key, log)| {
-                        offsets.get(&key).map(|offset| {
-                            let log = log.into_iter().enumerate().collect::<Vec<_>>();
-                            let sliced = log[*offset as usize..].to_vec();
-                            (key, sliced)
-                        })
-                    })
+                    .flatten()
+                    .filter(|(key, _)| keys.contains(*key))
+                    .map(|(key, offset)| (key.clone(), *offset))
                     .collect::<HashMap<_, _>>();
+                drop(logs);
 
-                node.reply(&message, json!({"msgs": msgs}))
+                node.reply(&message, json!({"offsets": offsets}))
                     .await
                     .expect("failed to send reply");
             }
-            Request::CommitOffsets { offsets: _ } => {
-                node.reply(&message, json!({}))
+            Request::OffsetsForTimes { timestamps } => {
+                let offsets = self
+                    .logs
+                    .read()
+                    .await
+                    .state()
+                    .offsets_for_times(&timestamps);
+
+                node.reply(&message, json!({"offsets": offsets}))
                     .await
                     .expect("failed to send reply");
             }
-            Request::ListCommittedOffsets { keys } => {
-                let logs = { self.logs.read().await.clone() };
-
-                let offsets = logs
+            Request::ListGroups => {
+                let groups = self
+                    .logs
+                    .read()
+                    .await
+                    .state()
+                    .groups()
                     .into_iter()
-                    .filter_map(|(key, log)| {
-                        if keys.contains(&key) {
-                            Some((key, log.len()))
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<HashMap<_, _>>();
+                    .cloned()
+                    .collect::<Vec<_>>();
 
-                node.reply(&message, json!({"offsets": offsets}))
+                node.reply(&message, json!({"groups": groups}))
                     .await
                     .expect("failed to send reply");
             }
@@ -83,6 +219,39 @@ impl Handler for KafkaHandler {
     }
 }
 
+/// Drops records that are out of the retention policy every second.
+///
+/// Logs are scanned under the read lock. Records appended in the meantime
+/// are not affected by the entries, so they stay valid until applied.
+async fn enforce_retention(logs: Arc<RwLock<Persistent<Logs>>>, retention: partition::Retention) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        let entries = logs
+            .read()
+            .await
+            .state()
+            .retain(&retention, partition::now_ms());
+        if entries.is_empty() {
+            continue;
+        }
+
+        let mut synced = Vec::new();
+        let mut logs = logs.write().await;
+        // logged, so recovery drops the same records
+        for entry in entries {
+            synced.push(
+                logs.apply_deferred(entry)
+                    .expect("failed to persist retention"),
+            );
+        }
+        drop(logs);
+        for synced in synced {
+            synced.wait().await.expect("failed to persist retention");
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let mut requests_rx = read_from_stdin().await;
@@ -91,7 +260,153 @@ async fn main() {
     let handle = spawn(write_to_stdout(responses_rx));
 
     let node = Node::initialize(&mut requests_rx, responses_tx.clone()).await;
-    node.listen(&mut requests_rx, KafkaHandler::default()).await;
+
+    // Logs are only kept on disk if asked to, so maelstrom runs start empty
+    let logs = if std::env::var_os("NODE_DATA_DIR").is_some() {
+        let dir = storage::node_dir(node.id).expect("failed to create data directory");
+        Persistent::open(dir.join("kafka"), FsyncPolicy::Always).expect("failed to recover logs")
+    } else {
+        Persistent::in_memory()
+    };
+    let handler = KafkaHandler {
+        logs: Arc::new(RwLock::new(logs)),
+    };
+
+    let interval = std::env::var("SNAPSHOT_INTERVAL_MS")
+        .ok()
+        .and_then(|ms| ms.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::from_secs(5));
+    spawn(storage::snapshot_periodically(
+        handler.logs.clone(),
+        interval,
+    ));
+
+    let retention = partition::Retention::from_env();
+    if retention.is_enabled() {
+        spawn(enforce_retention(handler.logs.clone(), retention));
+    }
+
+    node.listen(&mut requests_rx, handler).await;
 
     handle.await.expect("Task panic");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(body: serde_json::Value) -> Request {
+        let message = serde_json::from_value::<protocol::Message>(
+            json!({"src": "c1", "dest": "n0", "body": body}),
+        )
+        .unwrap();
+        message
+            .clone_into::<protocol::Request<Request>>()
+            .unwrap()
+            .payload
+    }
+
+    #[test]
+    fn request_types_are_known() {
+        let requests = [
+            Request::Send {
+                key: String::new(),
+                record: logs::Record::default(),
+            },
+            Request::Poll {
+                offsets: HashMap::new(),
+                limits: logs::Limits::default(),
+                metadata: false,
+            },
+            Request::CommitOffsets {
+                offsets: HashMap::new(),
+                group: None,
+                force: false,
+            },
+            Request::ListCommittedOffsets {
+                keys: HashSet::new(),
+                group: None,
+            },
+            Request::ListGroups,
+            Request::OffsetsForTimes {
+                timestamps: HashMap::new(),
+            },
+        ];
+        for request in requests {
+            let body = serde_json::to_value(request).unwrap();
+            assert!(REQUEST_TYPES.contains(&body["type"].as_str().unwrap()));
+        }
+    }
+
+    #[test]
+    fn send_integer_message() {
+        let request = parse(json!({"type": "send", "msg_id": 1, "key": "k", "msg": 5}));
+
+        let Request::Send { key, record } = request else {
+            panic!("not a send: {request:?}");
+        };
+        assert_eq!(key, "k");
+        assert_eq!(
+            record,
+            logs::Record {
+                msg: json!(5),
+                ..logs::Record::default()
+            }
+        );
+    }
+
+    #[test]
+    fn send_record_with_metadata() {
+        let body = json!({
+            "type": "send",
+            "msg_id": 1,
+            "key": "k",
+            "msg": {"text": "hello", "tags": [1, 2]},
+            "record_key": "user",
+            "headers": {"trace": "abc"},
+            "timestamp": 1700000000000u64,
+        });
+        let request = parse(body.clone());
+
+        let Request::Send { key, record } = &request else {
+            panic!("not a send: {request:?}");
+        };
+        assert_eq!(key, "k");
+        assert_eq!(record.msg, json!({"text": "hello", "tags": [1, 2]}));
+        assert_eq!(record.record_key.as_deref(), Some("user"));
+        assert_eq!(record.headers["trace"], "abc");
+        assert_eq!(record.timestamp, Some(1700000000000));
+
+        // serializes back to the same body
+        let request = protocol::Request {
+            msg_id: 1,
+            payload: request,
+        };
+        assert_eq!(serde_json::to_value(request).unwrap(), body);
+    }
+
+    #[test]
+    fn poll_with_limits_and_metadata() {
+        let request = parse(json!({
+            "type": "poll",
+            "msg_id": 1,
+            "offsets": {"k": 3},
+            "max_bytes": 100,
+            "metadata": true,
+        }));
+
+        let Request::Poll {
+            offsets,
+            limits,
+            metadata,
+        } = request
+        else {
+            panic!("not a poll: {request:?}");
+        };
+        assert_eq!(offsets["k"], 3);
+        assert_eq!(limits.max_bytes, Some(100));
+        assert_eq!(limits.max_messages, None);
+        assert!(metadata);
+    }
+}
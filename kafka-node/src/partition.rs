@@ -0,0 +1,368 @@
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::logs::Record;
+
+/// Number of records between entries of the time index.
+const INDEX_INTERVAL: usize = 32;
+
+/// Record with the position and time it was appended at.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredRecord")]
+pub struct Stored {
+    pub offset: usize,
+    /// Milliseconds since unix epoch, never decreases within a partition.
+    pub appended_at: u64,
+    pub record: Record,
+    /// [Record::size], which serializes the message, so it is only computed
+    /// once.
+    #[serde(skip)]
+    pub size: usize,
+}
+
+/// [Stored] as it is persisted, without the derived size.
+#[derive(Deserialize)]
+struct StoredRecord {
+    offset: usize,
+    appended_at: u64,
+    record: Record,
+}
+
+impl Stored {
+    fn new(offset: usize, appended_at: u64, record: Record) -> Self {
+        Self {
+            offset,
+            appended_at,
+            size: record.size(),
+            record,
+        }
+    }
+}
+
+impl From<StoredRecord> for Stored {
+    fn from(stored: StoredRecord) -> Self {
+        Self::new(stored.offset, stored.appended_at, stored.record)
+    }
+}
+
+/// Log of a single key.
+///
+/// Retention drops a prefix of the log and compaction drops records that are
+/// superseded by a later record with the same record key. Offsets of the
+/// remaining records never change, so the retained offsets start at the base
+/// offset and may have gaps.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Partition {
+    base_offset: usize,
+    next_offset: usize,
+    /// Retained records in offset order.
+    records: Vec<Stored>,
+    /// Append time and offset of every [INDEX_INTERVAL]th record, so time
+    /// lookups only scan records between two entries.
+    #[serde(default)]
+    time_index: Vec<(u64, usize)>,
+}
+
+/// How much of every log is retained. Unset limits retain everything.
+#[derive(Debug, Default, Clone)]
+pub struct Retention {
+    pub max_age: Option<Duration>,
+    pub max_bytes: Option<usize>,
+    /// Keep only the latest record of every record key.
+    pub compact: bool,
+}
+
+impl Retention {
+    /// Reads the policy from `KAFKA_RETENTION_MS`, `KAFKA_RETENTION_BYTES` and
+    /// `KAFKA_COMPACT`.
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok();
+        Self {
+            max_age: var("KAFKA_RETENTION_MS")
+                .and_then(|ms| ms.parse().ok())
+                .map(Duration::from_millis),
+            max_bytes: var("KAFKA_RETENTION_BYTES").and_then(|bytes| bytes.parse().ok()),
+            compact: var("KAFKA_COMPACT").is_some_and(|compact| compact == "true"),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_age.is_some() || self.max_bytes.is_some() || self.compact
+    }
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("clock before unix epoch")
+        .as_millis() as u64
+}
+
+impl Partition {
+    /// Offset of the first retained record.
+    pub fn base_offset(&self) -> usize {
+        self.base_offset
+    }
+
+    /// Offset the next appended record gets.
+    pub fn next_offset(&self) -> usize {
+        self.next_offset
+    }
+
+    /// Appends the record. Append time is clamped to the time of the
+    /// previous record, so it stays ordered even if the clock goes back.
+    pub fn append(&mut self, record: Record, appended_at: u64) -> usize {
+        let offset = self.next_offset;
+        let appended_at = self
+            .records
+            .last()
+            .map_or(appended_at, |last| appended_at.max(last.appended_at));
+        if offset.is_multiple_of(INDEX_INTERVAL) {
+            self.time_index.push((appended_at, offset));
+        }
+        self.records.push(Stored::new(offset, appended_at, record));
+        self.next_offset += 1;
+        offset
+    }
+
+    /// Returns retained records starting from offset, or from the base offset
+    /// if offset is no longer retained.
+    pub fn since(&self, offset: usize) -> &[Stored] {
+        let start = self
+            .records
+            .partition_point(|stored| stored.offset < offset);
+        &self.records[start..]
+    }
+
+    /// Drops records before offset.
+    pub fn truncate(&mut self, before: usize) {
+        let before = before.min(self.next_offset);
+        self.records.retain(|stored| stored.offset >= before);
+        self.time_index.retain(|(_, offset)| *offset >= before);
+        self.base_offset = self.base_offset.max(before);
+    }
+
+    /// Returns the earliest retained offset appended at or after timestamp.
+    pub fn offset_for_time(&self, timestamp: u64) -> Option<usize> {
+        // last indexed record before timestamp, the answer is after it
+        let indexed = self
+            .time_index
+            .partition_point(|(appended_at, _)| *appended_at < timestamp);
+        let from = indexed
+            .checked_sub(1)
+            .map_or(0, |indexed| self.time_index[indexed].1);
+        self.since(from)
+            .iter()
+            .find(|stored| stored.appended_at >= timestamp)
+            .map(|stored| stored.offset)
+    }
+
+    /// Offsets of records superseded by a later record with the same record
+    /// key.
+    fn superseded(&self) -> HashSet<usize> {
+        let mut keys = HashSet::new();
+        self.records
+            .iter()
+            .rev()
+            .filter(|stored| {
+                stored
+                    .record
+                    .record_key
+                    .as_ref()
+                    .is_some_and(|key| !keys.insert(key))
+            })
+            .map(|stored| stored.offset)
+            .collect()
+    }
+
+    /// Drops records superseded by a later record with the same record key.
+    pub fn compact(&mut self) {
+        let superseded = self.superseded();
+        self.records
+            .retain(|stored| !superseded.contains(&stored.offset));
+        if let Some(first) = self.records.first() {
+            self.base_offset = first.offset;
+        }
+    }
+
+    /// Whether compaction would drop anything.
+    pub fn is_compactable(&self) -> bool {
+        !self.superseded().is_empty()
+    }
+
+    /// Returns the offset before which retention drops records, if it drops
+    /// anything. With compaction, only records that survive it are counted.
+    pub fn expired_before(&self, retention: &Retention, now: u64) -> Option<usize> {
+        let superseded = if retention.compact {
+            self.superseded()
+        } else {
+            HashSet::new()
+        };
+        let retained = || {
+            self.records
+                .iter()
+                .filter(|stored| !superseded.contains(&stored.offset))
+        };
+
+        let mut bytes = retained().map(|stored| stored.size).sum::<usize>();
+        let mut before = None;
+        for stored in retained() {
+            let expired = retention.max_age.is_some_and(|max_age| {
+                now.saturating_sub(stored.appended_at) > max_age.as_millis() as u64
+            });
+            let oversized = retention
+                .max_bytes
+                .is_some_and(|max_bytes| bytes > max_bytes);
+            if !expired && !oversized {
+                break;
+            }
+            bytes -= stored.size;
+            before = Some(stored.offset + 1);
+        }
+        before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(msg: u32, record_key: Option<&str>) -> Record {
+        Record {
+            msg: msg.into(),
+            record_key: record_key.map(String::from),
+            ..Record::default()
+        }
+    }
+
+    fn offsets(records: &[Stored]) -> Vec<usize> {
+        records.iter().map(|stored| stored.offset).collect()
+    }
+
+    #[test]
+    fn truncate_keeps_offsets() {
+        let mut partition = Partition::default();
+        for msg in 0..5 {
+            partition.append(record(msg, None), 0);
+        }
+
+        partition.truncate(3);
+        assert_eq!(partition.base_offset(), 3);
+        assert_eq!(offsets(partition.since(0)), [3, 4]);
+        assert_eq!(offsets(partition.since(4)), [4]);
+        assert_eq!(partition.append(record(5, None), 0), 5);
+
+        partition.truncate(10);
+        assert_eq!(partition.base_offset(), 6);
+        assert!(partition.since(0).is_empty());
+    }
+
+    #[test]
+    fn compact_keeps_latest() {
+        let mut partition = Partition::default();
+        partition.append(record(0, Some("a")), 0);
+        partition.append(record(1, Some("b")), 0);
+        partition.append(record(2, None), 0);
+        partition.append(record(3, Some("a")), 0);
+        assert!(partition.is_compactable());
+
+        partition.compact();
+        assert_eq!(offsets(partition.since(0)), [1, 2, 3]);
+        assert_eq!(partition.base_offset(), 1);
+        assert!(!partition.is_compactable());
+    }
+
+    #[test]
+    fn retention_after_compaction() {
+        let mut partition = Partition::default();
+        for (msg, key) in [(0, "a"), (1, "a"), (2, "b"), (3, "a")] {
+            partition.append(record(msg, Some(key)), 100 * msg as u64);
+        }
+
+        // only b and the last a survive compaction, b is the one too old
+        let retention = Retention {
+            max_age: Some(Duration::from_millis(150)),
+            compact: true,
+            ..Retention::default()
+        };
+        assert_eq!(partition.expired_before(&retention, 400), Some(3));
+
+        // every message is 1 byte
+        let retention = Retention {
+            max_bytes: Some(1),
+            compact: true,
+            ..Retention::default()
+        };
+        assert_eq!(partition.expired_before(&retention, 0), Some(3));
+        let retention = Retention {
+            max_bytes: Some(2),
+            compact: true,
+            ..Retention::default()
+        };
+        assert_eq!(partition.expired_before(&retention, 0), None);
+    }
+
+    #[test]
+    fn size_survives_restore() {
+        let mut partition = Partition::default();
+        partition.append(record(100, None), 0);
+
+        let raw = serde_json::to_string(&partition).unwrap();
+        let restored = serde_json::from_str::<Partition>(&raw).unwrap();
+        assert_eq!(restored.since(0)[0].size, 3);
+        assert_eq!(restored, partition);
+    }
+
+    #[test]
+    fn offset_for_time() {
+        let mut partition = Partition::default();
+        for msg in 0..100 {
+            partition.append(record(msg, None), 1000 + 10 * msg as u64);
+        }
+
+        assert_eq!(partition.offset_for_time(0), Some(0));
+        assert_eq!(partition.offset_for_time(1500), Some(50));
+        assert_eq!(partition.offset_for_time(1501), Some(51));
+        assert_eq!(partition.offset_for_time(1990), Some(99));
+        assert_eq!(partition.offset_for_time(1991), None);
+
+        partition.truncate(60);
+        assert_eq!(partition.offset_for_time(1500), Some(60));
+    }
+
+    #[test]
+    fn append_time_never_decreases() {
+        let mut partition = Partition::default();
+        partition.append(record(0, None), 200);
+        partition.append(record(1, None), 100);
+
+        assert_eq!(partition.since(1)[0].appended_at, 200);
+        assert_eq!(partition.offset_for_time(150), Some(0));
+    }
+
+    #[test]
+    fn retention() {
+        let mut partition = Partition::default();
+        for (msg, appended_at) in [(10, 100), (20, 200), (30, 300)] {
+            partition.append(record(msg, None), appended_at);
+        }
+
+        let by_age = Retention {
+            max_age: Some(Duration::from_millis(150)),
+            ..Retention::default()
+        };
+        assert_eq!(partition.expired_before(&by_age, 300), Some(1));
+        assert_eq!(partition.expired_before(&by_age, 400), Some(2));
+
+        // every message is 2 bytes
+        let by_size = Retention {
+            max_bytes: Some(3),
+            ..Retention::default()
+        };
+        assert_eq!(partition.expired_before(&by_size, 0), Some(2));
+
+        assert_eq!(partition.expired_before(&Retention::default(), 1000), None);
+    }
+}
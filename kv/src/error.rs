@@ -0,0 +1,111 @@
+use maelstrom_node::{ErrorCode, ErrorResponse, SendError};
+
+#[derive(Debug)]
+pub enum KvError {
+    /// Key does not exist.
+    NotFound,
+    /// Compare-and-set failed, because the current value did not match.
+    CasMismatch,
+    Timeout,
+    /// Any other error reported by the store.
+    Unavailable(ErrorResponse),
+    /// Value could not be encoded or decoded.
+    Decode(serde_json::Error),
+}
+
+impl std::fmt::Display for KvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "key does not exist"),
+            Self::CasMismatch => write!(f, "current value does not match"),
+            Self::Timeout => write!(f, "timeout"),
+            Self::Unavailable(error) => write!(f, "{error}"),
+            Self::Decode(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for KvError {}
+
+impl From<SendError> for KvError {
+    fn from(value: SendError) -> Self {
+        match value {
+            SendError::Json(error) => Self::Decode(error),
+            SendError::Response(error) => match error.code {
+                ErrorCode::KeyDoesNotExist => Self::NotFound,
+                ErrorCode::PreconditionFailed => Self::CasMismatch,
+                ErrorCode::Timeout => Self::Timeout,
+                _ => Self::Unavailable(error),
+            },
+        }
+    }
+}
+
+/// Reply to a request that failed because of the store. Errors other than
+/// timeouts and the store's own are not expected, so they are reported as a
+/// crash, which leaves the outcome of the request indefinite.
+impl From<KvError> for ErrorResponse {
+    fn from(value: KvError) -> Self {
+        match value {
+            KvError::Unavailable(error) => error,
+            error => Self {
+                code: match error {
+                    KvError::Timeout => ErrorCode::Timeout,
+                    _ => ErrorCode::Crash,
+                },
+                text: error.to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_send_error() {
+        let error = |code| {
+            KvError::from(SendError::Response(ErrorResponse {
+                code,
+                text: String::new(),
+            }))
+        };
+        assert!(matches!(
+            error(ErrorCode::KeyDoesNotExist),
+            KvError::NotFound
+        ));
+        assert!(matches!(
+            error(ErrorCode::PreconditionFailed),
+            KvError::CasMismatch
+        ));
+        assert!(matches!(error(ErrorCode::Timeout), KvError::Timeout));
+        assert!(matches!(
+            error(ErrorCode::Crash),
+            KvError::Unavailable(ErrorResponse {
+                code: ErrorCode::Crash,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn into_error_response() {
+        let unavailable = ErrorResponse {
+            code: ErrorCode::TemporarilyUnavailable,
+            text: String::from("overloaded"),
+        };
+        assert_eq!(
+            ErrorResponse::from(KvError::Unavailable(unavailable)).code,
+            ErrorCode::TemporarilyUnavailable
+        );
+        assert_eq!(
+            ErrorResponse::from(KvError::Timeout).code,
+            ErrorCode::Timeout
+        );
+        assert_eq!(
+            ErrorResponse::from(KvError::CasMismatch).code,
+            ErrorCode::Crash
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::{KeyValueStore, KvError};
+
+/// Named lease, held by at most one owner at a time.
+///
+/// The lease is a `{owner, expires_at}` record, which is only ever replaced by
+/// a CAS, so an owner can't take over a lease that changed since it was read.
+/// Expiration is compared against wall clock, so owners must agree on time
+/// within a fraction of the lease duration.
+#[derive(Clone)]
+pub struct Lease<S> {
+    store: S,
+    key: String,
+    owner: String,
+    duration: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Record {
+    owner: String,
+    /// Milliseconds since unix epoch.
+    expires_at: u64,
+}
+
+impl Record {
+    fn expired(&self) -> bool {
+        self.expires_at <= now()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("clock before unix epoch")
+        .as_millis() as u64
+}
+
+impl<S: KeyValueStore> Lease<S> {
+    pub fn new(store: S, name: impl ToString, owner: impl ToString, duration: Duration) -> Self {
+        Self {
+            store,
+            key: format!("lease_{}", name.to_string()),
+            owner: owner.to_string(),
+            duration,
+        }
+    }
+
+    /// Makes a single attempt to acquire or extend the lease. Returns the
+    /// local deadline until which the lease is held, or None if it is held
+    /// by someone else.
+    async fn try_extend(&self) -> Result<Option<Instant>, KvError> {
+        // measured before the CAS, so the local deadline never outlives the
+        // stored one
+        let deadline = Instant::now() + self.duration;
+        let to = Record {
+            owner: self.owner.clone(),
+            expires_at: now() + self.duration.as_millis() as u64,
+        };
+
+        let result = match self.store.read_opt::<Record>(&self.key).await? {
+            None => self.store.cas(&self.key, &to, &to, true).await,
+            Some(from) if from.owner == self.owner || from.expired() => {
+                self.store.cas(&self.key, from, &to, false).await
+            }
+            Some(_) => return Ok(None),
+        };
+        match result {
+            Ok(()) => Ok(Some(deadline)),
+            // lost the race to another owner
+            Err(KvError::CasMismatch | KvError::NotFound) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Makes a single attempt to acquire the lease.
+    pub async fn try_acquire(&self) -> Result<Option<Leadership<S>>, KvError> {
+        Ok(self
+            .try_extend()
+            .await?
+            .map(|deadline| Leadership::start(self.clone(), deadline)))
+    }
+
+    /// Waits until the lease is acquired.
+    pub async fn acquire(&self) -> Result<Leadership<S>, KvError> {
+        loop {
+            if let Some(leadership) = self.try_acquire().await? {
+                return Ok(leadership);
+            }
+            tokio::time::sleep(self.duration / 3).await;
+        }
+    }
+
+    /// Renews the lease until it is lost, with several attempts per lease
+    /// duration, so a few failed renewals don't lose it. Every attempt is
+    /// bounded by the current deadline, so a stalled store can't keep the
+    /// lease held past it.
+    async fn renew(self, mut deadline: Instant, held: watch::Sender<Option<Instant>>) {
+        loop {
+            tokio::time::sleep_until(deadline.min(Instant::now() + self.duration / 3)).await;
+            match tokio::time::timeout_at(deadline, self.try_extend()).await {
+                Ok(Ok(Some(extended))) => {
+                    deadline = extended;
+                    held.send_replace(Some(deadline));
+                }
+                Ok(Ok(None)) => break,
+                Ok(Err(error)) => log::warn!("failed to renew lease {}: {error}", self.key),
+                Err(_) => {
+                    log::warn!("renewal of lease {} outlived the lease", self.key);
+                    break;
+                }
+            }
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
+        held.send_replace(None);
+    }
+
+    async fn release(&self) -> Result<(), KvError> {
+        let Some(from) = self.store.read_opt::<Record>(&self.key).await? else {
+            return Ok(());
+        };
+        if from.owner != self.owner {
+            return Ok(());
+        }
+        let to = Record {
+            owner: self.owner.clone(),
+            expires_at: 0,
+        };
+        match self.store.cas(&self.key, from, to, false).await {
+            // the lease has already been taken over
+            Err(KvError::CasMismatch) => Ok(()),
+            result => result,
+        }
+    }
+}
+
+/// Held lease, renewed in the background until it is lost or released.
+pub struct Leadership<S> {
+    lease: Lease<S>,
+    /// Local deadline of the lease, None once it is lost.
+    held: watch::Receiver<Option<Instant>>,
+    renewal: JoinHandle<()>,
+}
+
+impl<S: KeyValueStore> Leadership<S> {
+    fn start(lease: Lease<S>, deadline: Instant) -> Self {
+        let (held_tx, held) = watch::channel(Some(deadline));
+        let renewal = tokio::spawn(lease.clone().renew(deadline, held_tx));
+        Self {
+            lease,
+            held,
+            renewal,
+        }
+    }
+
+    /// Whether the lease is still held. Once lost, the lease is not
+    /// reacquired.
+    pub fn is_held(&self) -> bool {
+        self.held
+            .borrow()
+            .is_some_and(|deadline| Instant::now() < deadline)
+    }
+
+    /// Completes when the lease is lost.
+    pub async fn lost(&mut self) {
+        // the sender is only dropped after reporting the loss
+        let _ = self.held.wait_for(Option::is_none).await;
+    }
+
+    /// Stops renewing the lease and lets others acquire it right away.
+    pub async fn release(self) -> Result<(), KvError> {
+        self.renewal.abort();
+        if !self.is_held() {
+            return Ok(());
+        }
+        self.lease.release().await
+    }
+}
+
+impl<S> Drop for Leadership<S> {
+    fn drop(&mut self) {
+        self.renewal.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{Consistency, MemoryKV};
+
+    const DURATION: Duration = Duration::from_millis(60);
+
+    #[tokio::test]
+    async fn single_owner() {
+        let store = MemoryKV::new(Consistency::Linearizable);
+        let a = Lease::new(store.client(), "leader", "n0", DURATION);
+        let b = Lease::new(store.client(), "leader", "n1", DURATION);
+
+        let leadership = a.try_acquire().await.unwrap().unwrap();
+        assert!(b.try_acquire().await.unwrap().is_none());
+
+        // renewals keep the lease past its duration
+        tokio::time::sleep(DURATION * 2).await;
+        assert!(leadership.is_held());
+        assert!(b.try_acquire().await.unwrap().is_none());
+
+        leadership.release().await.unwrap();
+        assert!(b.try_acquire().await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn takeover_after_expiration() {
+        let store = MemoryKV::new(Consistency::Linearizable);
+        let a = Lease::new(store.client(), "leader", "n0", DURATION);
+        let b = Lease::new(store.client(), "leader", "n1", DURATION);
+
+        // dropped leadership is no longer renewed
+        drop(a.try_acquire().await.unwrap().unwrap());
+        assert!(b.try_acquire().await.unwrap().is_none());
+
+        let leadership = b.acquire().await.unwrap();
+        assert!(leadership.is_held());
+    }
+
+    #[tokio::test]
+    async fn reports_loss() {
+        let store = MemoryKV::new(Consistency::Linearizable);
+        let a = Lease::new(store.client(), "leader", "n0", DURATION);
+
+        let mut leadership = a.try_acquire().await.unwrap().unwrap();
+        store
+            .write(
+                "lease_leader",
+                Record {
+                    owner: "n1".to_string(),
+                    expires_at: u64::MAX,
+                },
+            )
+            .await
+            .unwrap();
+
+        leadership.lost().await;
+        assert!(!leadership.is_held());
+    }
+
+    /// Store whose operations hang while stalled.
+    #[derive(Clone)]
+    struct Stalling {
+        store: MemoryKV,
+        stalled: Arc<AtomicBool>,
+    }
+
+    impl Stalling {
+        async fn stall(&self) {
+            if self.stalled.load(Ordering::SeqCst) {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+
+    impl KeyValueStore for Stalling {
+        async fn sync(&self) -> Result<(), KvError> {
+            self.stall().await;
+            self.store.sync().await
+        }
+
+        async fn read<R: serde::de::DeserializeOwned + Send>(
+            &self,
+            key: impl ToString + Send,
+        ) -> Result<R, KvError> {
+            self.stall().await;
+            self.store.read(key).await
+        }
+
+        async fn write(
+            &self,
+            key: impl ToString + Send,
+            value: impl Serialize + Send,
+        ) -> Result<(), KvError> {
+            self.stall().await;
+            self.store.write(key, value).await
+        }
+
+        async fn cas(
+            &self,
+            key: impl ToString + Send,
+            from: impl Serialize + Send,
+            to: impl Serialize + Send,
+            create_if_not_exists: bool,
+        ) -> Result<(), KvError> {
+            self.stall().await;
+            self.store.cas(key, from, to, create_if_not_exists).await
+        }
+    }
+
+    #[tokio::test]
+    async fn stalled_renewal_loses_lease() {
+        let store = MemoryKV::new(Consistency::Linearizable);
+        let stalling = Stalling {
+            store: store.client(),
+            stalled: Arc::default(),
+        };
+        let a = Lease::new(stalling.clone(), "leader", "n0", DURATION);
+        let b = Lease::new(store.client(), "leader", "n1", DURATION);
+
+        let mut leadership = a.try_acquire().await.unwrap().unwrap();
+        stalling.stalled.store(true, Ordering::SeqCst);
+
+        tokio::time::timeout(DURATION * 2, leadership.lost())
+            .await
+            .expect("stalled lease is not reported lost");
+        assert!(!leadership.is_held());
+        assert!(b.try_acquire().await.unwrap().is_some());
+    }
+}
@@ -1,10 +1,130 @@
-use maelstrom_node::{ids, Node, SendError};
+use std::future::Future;
+use std::sync::{atomic, Arc};
+use std::time::Duration;
+
+mod error;
+mod lease;
+mod memory;
+
+use maelstrom_node::{ids, Node};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+pub use error::KvError;
+pub use lease::{Leadership, Lease};
+pub use memory::{Consistency, MemoryKV};
+
+/// How many times [KeyValueStore::update] tries to commit before giving up.
+pub const UPDATE_ATTEMPTS: usize = 10;
+
+/// Delay before the second [KeyValueStore::update] attempt, doubles with every
+/// attempt.
+const UPDATE_BACKOFF: Duration = Duration::from_millis(5);
+
+/// Key-value store with the interface of maelstrom `seq-kv` and `lin-kv`.
+///
+/// Clones share the view of the store, so a clone handed to a spawned task
+/// acts on behalf of the same node.
+pub trait KeyValueStore: Clone + Send + Sync + 'static {
+    /// Makes sure that following reads observe every write that the store had
+    /// accepted before the call.
+    fn sync(&self) -> impl Future<Output = Result<(), KvError>> + Send;
+
+    fn read<R: DeserializeOwned + Send>(
+        &self,
+        key: impl ToString + Send,
+    ) -> impl Future<Output = Result<R, KvError>> + Send;
+
+    fn write(
+        &self,
+        key: impl ToString + Send,
+        value: impl Serialize + Send,
+    ) -> impl Future<Output = Result<(), KvError>> + Send;
+
+    fn cas(
+        &self,
+        key: impl ToString + Send,
+        from: impl Serialize + Send,
+        to: impl Serialize + Send,
+        create_if_not_exists: bool,
+    ) -> impl Future<Output = Result<(), KvError>> + Send;
+
+    /// Same as [KeyValueStore::read], but a missing key is not an error.
+    fn read_opt<R: DeserializeOwned + Send>(
+        &self,
+        key: impl ToString + Send,
+    ) -> impl Future<Output = Result<Option<R>, KvError>> + Send {
+        let read = self.read(key);
+        async move {
+            match read.await {
+                Ok(value) => Ok(Some(value)),
+                Err(KvError::NotFound) => Ok(None),
+                Err(error) => Err(error),
+            }
+        }
+    }
+
+    /// Reads the latest value of the key, even from a sequential store.
+    fn read_fresh<R: DeserializeOwned + Send>(
+        &self,
+        key: impl ToString + Send,
+    ) -> impl Future<Output = Result<R, KvError>> + Send {
+        let store = self.clone();
+        let key = key.to_string();
+        async move {
+            store.sync().await?;
+            store.read(key).await
+        }
+    }
+
+    /// Atomically replaces the value of the key with the result of update,
+    /// starting from default if the key does not exist. Update may be called
+    /// several times, if the value is concurrently modified.
+    ///
+    /// Returns the committed value.
+    fn update<V>(
+        &self,
+        key: impl ToString + Send,
+        default: V,
+        mut update: impl FnMut(V) -> V + Send,
+    ) -> impl Future<Output = Result<V, KvError>> + Send
+    where
+        V: Serialize + DeserializeOwned + Clone + Send + Sync,
+    {
+        let store = self.clone();
+        let key = key.to_string();
+        async move {
+            let mut backoff = UPDATE_BACKOFF;
+            let mut attempt = 1;
+            loop {
+                let current = store.read_opt::<V>(&key).await?;
+
+                let exists = current.is_some();
+                let from = current.unwrap_or_else(|| default.clone());
+                let to = update(from.clone());
+
+                match store.cas(&key, from, to.clone(), !exists).await {
+                    Ok(()) => return Ok(to),
+                    Err(KvError::CasMismatch | KvError::NotFound) if attempt < UPDATE_ATTEMPTS => {
+                        // Lost the race to a concurrent update, try again
+                        tokio::time::sleep(backoff).await;
+                        backoff *= 2;
+                        attempt += 1;
+                    }
+                    Err(error) => return Err(error),
+                }
+            }
+        }
+    }
+}
+
+/// Client of the maelstrom key-value services.
 #[derive(Clone)]
 pub struct KV {
     id: ids::Store,
     node: Node,
+
+    /// Last value of this node's freshness token, see [KeyValueStore::sync].
+    token: Arc<atomic::AtomicU64>,
 }
 
 impl KV {
@@ -12,6 +132,7 @@ impl KV {
         Self {
             node,
             id: ids::Store::Seq,
+            token: Arc::new(atomic::AtomicU64::new(0)),
         }
     }
 
@@ -19,10 +140,51 @@ impl KV {
         Self {
             node,
             id: ids::Store::Lin,
+            token: Arc::new(atomic::AtomicU64::new(0)),
         }
     }
+}
 
-    pub async fn read<R: DeserializeOwned>(&self, key: impl ToString) -> Result<R, SendError> {
+impl KeyValueStore for KV {
+    /// Makes sure that following reads by this node observe every write that
+    /// the store had accepted before the call.
+    ///
+    /// Sequential store may serve reads from a stale state, but never from a
+    /// state older than the node's own previous operations. So the node
+    /// advances its own token with a CAS, which seq-kv always applies to the
+    /// latest state, and following reads observe at least that state. The
+    /// token value itself proves nothing: a stale state taken after the
+    /// node's previous sync holds the same value. Linearizable store needs
+    /// nothing.
+    async fn sync(&self) -> Result<(), KvError> {
+        if self.id == ids::Store::Lin {
+            return Ok(());
+        }
+
+        let key = format!("{}_token", self.node.id);
+        loop {
+            let from = self.token.load(atomic::Ordering::SeqCst);
+            match self.cas(&key, from, from + 1, from == 0).await {
+                Ok(()) => {
+                    // Concurrent syncs may have advanced the token further
+                    self.token.fetch_max(from + 1, atomic::Ordering::SeqCst);
+                    return Ok(());
+                }
+                Err(KvError::CasMismatch) => {
+                    // Token was advanced by a concurrent sync, or by a previous
+                    // run of this node. Continue from the stored value.
+                    let current = self.read::<u64>(&key).await?;
+                    self.token.fetch_max(current, atomic::Ordering::SeqCst);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    async fn read<R: DeserializeOwned + Send>(
+        &self,
+        key: impl ToString + Send,
+    ) -> Result<R, KvError> {
         #[derive(Serialize)]
         #[serde(tag = "type", rename = "read")]
         struct ReadRequest {
@@ -47,7 +209,11 @@ impl KV {
         Ok(response.value)
     }
 
-    pub async fn write(&self, key: impl ToString, value: impl Serialize) -> Result<(), SendError> {
+    async fn write(
+        &self,
+        key: impl ToString + Send,
+        value: impl Serialize + Send,
+    ) -> Result<(), KvError> {
         #[derive(Serialize)]
         #[serde(tag = "type", rename = "write")]
         struct WriteRequest<V> {
@@ -72,13 +238,13 @@ impl KV {
         Ok(())
     }
 
-    pub async fn cas(
+    async fn cas(
         &self,
-        key: impl ToString,
-        from: impl Serialize,
-        to: impl Serialize,
+        key: impl ToString + Send,
+        from: impl Serialize + Send,
+        to: impl Serialize + Send,
         create_if_not_exists: bool,
-    ) -> Result<(), SendError> {
+    ) -> Result<(), KvError> {
         #[derive(Serialize)]
         #[serde(tag = "type", rename = "cas")]
         struct CasRequest<F, T> {
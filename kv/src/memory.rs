@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::sync::{atomic, Arc, Mutex};
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{KeyValueStore, KvError};
+
+/// Which states of [MemoryKV] reads may observe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Consistency {
+    /// Reads observe every accepted write, like `lin-kv`.
+    Linearizable,
+    /// Reads observe the state as of the client's own last write or sync, but
+    /// nothing newer, like the stalest state `seq-kv` is allowed to serve.
+    Sequential,
+}
+
+/// In-memory store, which lets handlers be tested without maelstrom.
+///
+/// Every write creates a new version of the store. Writes and CAS always act on
+/// the latest version, reads act on the version allowed by [Consistency].
+/// [MemoryKV::client] connects another client, e.g. another node, to the same
+/// store.
+#[derive(Clone)]
+pub struct MemoryKV {
+    consistency: Consistency,
+    state: Arc<Mutex<State>>,
+
+    /// Latest version observed by this client.
+    seen: Arc<atomic::AtomicU64>,
+}
+
+#[derive(Default)]
+struct State {
+    version: u64,
+    /// History of every key, as pairs of version and value.
+    keys: HashMap<String, Vec<(u64, Value)>>,
+}
+
+impl State {
+    fn get(&self, key: &str, version: u64) -> Option<&Value> {
+        self.keys
+            .get(key)?
+            .iter()
+            .rev()
+            .find(|(written, _)| *written <= version)
+            .map(|(_, value)| value)
+    }
+
+    fn put(&mut self, key: String, value: Value) -> u64 {
+        self.version += 1;
+        self.keys
+            .entry(key)
+            .or_default()
+            .push((self.version, value));
+        self.version
+    }
+}
+
+impl MemoryKV {
+    pub fn new(consistency: Consistency) -> Self {
+        Self {
+            consistency,
+            state: Arc::default(),
+            seen: Arc::default(),
+        }
+    }
+
+    /// Returns a new client of the same store, which has not observed anything
+    /// yet.
+    pub fn client(&self) -> Self {
+        Self {
+            consistency: self.consistency,
+            state: self.state.clone(),
+            seen: Arc::default(),
+        }
+    }
+
+    fn observe(&self, version: u64) {
+        self.seen.fetch_max(version, atomic::Ordering::SeqCst);
+    }
+}
+
+impl KeyValueStore for MemoryKV {
+    async fn sync(&self) -> Result<(), KvError> {
+        let version = self.state.lock().unwrap().version;
+        self.observe(version);
+        Ok(())
+    }
+
+    async fn read<R: DeserializeOwned + Send>(
+        &self,
+        key: impl ToString + Send,
+    ) -> Result<R, KvError> {
+        let state = self.state.lock().unwrap();
+        let version = match self.consistency {
+            Consistency::Linearizable => state.version,
+            Consistency::Sequential => self.seen.load(atomic::Ordering::SeqCst),
+        };
+        let value = state
+            .get(&key.to_string(), version)
+            .ok_or(KvError::NotFound)?;
+        serde_json::from_value(value.clone()).map_err(KvError::Decode)
+    }
+
+    async fn write(
+        &self,
+        key: impl ToString + Send,
+        value: impl Serialize + Send,
+    ) -> Result<(), KvError> {
+        let value = serde_json::to_value(value).map_err(KvError::Decode)?;
+        let version = self.state.lock().unwrap().put(key.to_string(), value);
+        self.observe(version);
+        Ok(())
+    }
+
+    async fn cas(
+        &self,
+        key: impl ToString + Send,
+        from: impl Serialize + Send,
+        to: impl Serialize + Send,
+        create_if_not_exists: bool,
+    ) -> Result<(), KvError> {
+        let key = key.to_string();
+        let from = serde_json::to_value(from).map_err(KvError::Decode)?;
+        let to = serde_json::to_value(to).map_err(KvError::Decode)?;
+
+        let mut state = self.state.lock().unwrap();
+        // CAS acts on the latest version, so the client observes it either way
+        self.observe(state.version);
+        match state.get(&key, state.version) {
+            Some(current) if *current != from => return Err(KvError::CasMismatch),
+            None if !create_if_not_exists => return Err(KvError::NotFound),
+            _ => {}
+        }
+        let version = state.put(key, to);
+        self.observe(version);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn linearizable_reads_latest() {
+        let a = MemoryKV::new(Consistency::Linearizable);
+        let b = a.client();
+
+        a.write("x", 1).await.unwrap();
+        assert_eq!(b.read::<i64>("x").await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn sequential_reads_are_stale_until_sync() {
+        let a = MemoryKV::new(Consistency::Sequential);
+        let b = a.client();
+
+        a.write("x", 1).await.unwrap();
+        assert_eq!(a.read::<i64>("x").await.unwrap(), 1);
+        assert!(matches!(b.read::<i64>("x").await, Err(KvError::NotFound)));
+
+        b.write("y", 2).await.unwrap();
+        a.write("x", 3).await.unwrap();
+        assert_eq!(b.read::<i64>("x").await.unwrap(), 1);
+
+        assert_eq!(b.read_fresh::<i64>("x").await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn cas() {
+        let kv = MemoryKV::new(Consistency::Linearizable);
+
+        assert!(matches!(
+            kv.cas("x", 0, 1, false).await,
+            Err(KvError::NotFound)
+        ));
+        kv.cas("x", 0, 1, true).await.unwrap();
+        assert!(matches!(
+            kv.cas("x", 0, 2, true).await,
+            Err(KvError::CasMismatch)
+        ));
+        kv.cas("x", 1, 2, false).await.unwrap();
+        assert_eq!(kv.read::<i64>("x").await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn concurrent_updates() {
+        let kv = MemoryKV::new(Consistency::Sequential);
+
+        let updates = (0..5)
+            .map(|_| {
+                let client = kv.client();
+                tokio::spawn(async move { client.update("x", 0, |x: i64| x + 1).await })
+            })
+            .collect::<Vec<_>>();
+        for update in updates {
+            update.await.unwrap().unwrap();
+        }
+
+        assert_eq!(kv.read_fresh::<i64>("x").await.unwrap(), 5);
+    }
+}
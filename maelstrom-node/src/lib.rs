@@ -12,6 +12,7 @@ use tokio::{io, spawn, sync};
 
 pub mod ids;
 pub mod protocol;
+pub mod storage;
 
 pub trait Handler {
     fn handle(&self, node: Node, message: protocol::Message) -> impl Future<Output = ()> + Send;
@@ -51,7 +52,8 @@ pub async fn read_from_stdin() -> sync::mpsc::Receiver<protocol::Message> {
 #[derive(Clone)]
 pub struct Node {
     pub id: ids::NodeId,
-    pub node_ids: Vec<ids::NodeId>,
+
+    node_ids: Arc<std::sync::RwLock<Vec<ids::NodeId>>>,
 
     latest_message_id: Arc<atomic::AtomicU64>,
     waiting_for: Arc<RwLock<HashMap<u64, sync::oneshot::Sender<protocol::Response>>>>,
@@ -153,13 +155,37 @@ impl Node {
     ) -> Self {
         Self {
             id,
-            node_ids,
+            node_ids: Arc::new(std::sync::RwLock::new(node_ids)),
             latest_message_id: Arc::new(atomic::AtomicU64::new(0)),
             waiting_for: Arc::new(RwLock::new(HashMap::new())),
             responses_tx,
         }
     }
 
+    /// Current cluster membership, including this node.
+    pub fn node_ids(&self) -> Vec<ids::NodeId> {
+        self.node_ids.read().expect("Lock poisoned").clone()
+    }
+
+    /// Adds a node to the membership. Returns false if it was already a member.
+    pub fn add_node(&self, node_id: ids::NodeId) -> bool {
+        let mut node_ids = self.node_ids.write().expect("Lock poisoned");
+        if node_ids.contains(&node_id) {
+            false
+        } else {
+            node_ids.push(node_id);
+            true
+        }
+    }
+
+    /// Removes a node from the membership. Returns false if it was not a member.
+    pub fn remove_node(&self, node_id: ids::NodeId) -> bool {
+        let mut node_ids = self.node_ids.write().expect("Lock poisoned");
+        let len = node_ids.len();
+        node_ids.retain(|id| id != &node_id);
+        node_ids.len() != len
+    }
+
     pub async fn listen(
         &self,
         requests_tx: &mut sync::mpsc::Receiver<protocol::Message>,
@@ -196,6 +222,20 @@ impl Node {
         Ok(())
     }
 
+    /// Sends a message that does not expect a reply.
+    pub async fn notify(
+        &self,
+        dest: ids::PeerId,
+        body: impl Serialize,
+    ) -> Result<(), serde_json::Error> {
+        let notification = protocol::Message::notification_to(self.id, dest, body)?;
+        self.responses_tx
+            .send(notification)
+            .await
+            .expect("Channel error");
+        Ok(())
+    }
+
     pub async fn send<R: DeserializeOwned>(
         &self,
         dest: ids::PeerId,
@@ -249,3 +289,20 @@ fn test_error() {
     assert_eq!(error.code, ErrorCode::KeyDoesNotExist);
     assert_eq!(error.text, "key does not exist");
 }
+
+#[test]
+fn test_error_reply() {
+    let raw = r#"{"src":"c1","dest":"n0","body":{"type":"read","msg_id":3}}"#;
+    let request =
+        serde_json::from_str::<protocol::Message>(raw).expect("failed to parse as message");
+    let error = ErrorResponse {
+        code: ErrorCode::KeyDoesNotExist,
+        text: String::from("not found"),
+    };
+
+    let reply = protocol::Message::reply_for(&request, error).expect("failed to make reply");
+    assert_eq!(
+        serde_json::to_string(&reply).expect("failed to serialize"),
+        r#"{"src":"n0","dest":"c1","body":{"code":20,"in_reply_to":3,"text":"not found","type":"error"}}"#
+    );
+}
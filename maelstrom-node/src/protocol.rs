@@ -25,6 +25,10 @@ impl Message {
         }
     }
 
+    pub fn message_type(&self) -> Option<&str> {
+        self.body.get("type")?.as_str()
+    }
+
     pub fn clone_into<P: DeserializeOwned>(&self) -> Result<P, serde_json::Error> {
         serde_json::from_value(serde_json::Value::Object(self.body.clone()))
     }
@@ -47,6 +51,24 @@ impl Message {
         })
     }
 
+    pub fn notification_to<B: Serialize>(
+        src: ids::NodeId,
+        dest: ids::PeerId,
+        payload: B,
+    ) -> Result<Self, serde_json::Error> {
+        let serde_json::Value::Object(body) = serde_json::to_value(payload)? else {
+            return Err(serde_json::Error::custom("payload is not an object"));
+        };
+
+        Ok(Self {
+            src: src.into(),
+            dest,
+            body,
+        })
+    }
+
+    /// Makes a reply to the request. The reply type is `<request type>_ok`,
+    /// unless the payload has a `type` of its own, which is kept.
     pub fn reply_for<B: Serialize>(
         message: &Message,
         payload: B,
@@ -67,10 +89,9 @@ impl Message {
         };
 
         body.insert(String::from("in_reply_to"), msg_id.clone());
-        body.insert(
-            String::from("type"),
-            serde_json::Value::String(format!("{request_type}_ok")),
-        );
+        // payloads with explicit type, such as errors, keep it
+        body.entry("type")
+            .or_insert_with(|| serde_json::Value::String(format!("{request_type}_ok")));
 
         Ok(Self {
             src: message.dest,
@@ -99,3 +120,34 @@ impl Response {
         serde_json::from_value(serde_json::Value::Object(self.payload))
     }
 }
+
+#[test]
+fn test_reply_for() {
+    let raw = r#"{"src":"c1","dest":"n0","body":{"type":"read","msg_id":3}}"#;
+    let request = serde_json::from_str::<Message>(raw).expect("failed to parse as message");
+
+    let reply = Message::reply_for(&request, serde_json::json!({"value": 1}))
+        .expect("failed to make reply");
+    assert_eq!(
+        serde_json::to_string(&reply).expect("failed to serialize"),
+        r#"{"src":"n0","dest":"c1","body":{"in_reply_to":3,"type":"read_ok","value":1}}"#
+    );
+}
+
+#[test]
+fn test_reply_for_type() {
+    let raw = r#"{"src":"c1","dest":"n0","body":{"type":"gossip","msg_id":3}}"#;
+    let request = serde_json::from_str::<Message>(raw).expect("failed to parse as message");
+
+    let implicit = Message::reply_for(&request, serde_json::json!({"counters": {}}))
+        .expect("failed to make reply");
+    assert_eq!(implicit.message_type(), Some("gossip_ok"));
+
+    let explicit = Message::reply_for(
+        &request,
+        serde_json::json!({"type": "gossip_state", "counters": {}}),
+    )
+    .expect("failed to make reply");
+    assert_eq!(explicit.message_type(), Some("gossip_state"));
+    assert_eq!(explicit.in_reply_to(), Some(3));
+}
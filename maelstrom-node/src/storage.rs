@@ -0,0 +1,745 @@
+//! Local persistence, so handlers can survive process restarts.
+//!
+//! Everything is built on [Wal], an append-only file of JSON lines. A crash
+//! may leave the last line torn, such line is dropped on recovery. Any other
+//! unreadable line is corruption, and recovery fails instead of dropping the
+//! entries after it.
+//!
+//! Async callers flush and snapshot on the blocking thread pool, see
+//! [Persistent::apply_deferred] and [snapshot_periodically].
+
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::io::{self, BufRead, Seek, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::ids;
+
+/// When appended entries are flushed to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// After every entry, nothing acknowledged is lost on power failure.
+    Always,
+    /// After every n entries, up to n - 1 entries may be lost.
+    Batch(usize),
+    /// Never explicitly, entries survive process crashes, but not power
+    /// failures.
+    Never,
+}
+
+/// Returns the directory for the node's files, `$NODE_DATA_DIR/<node id>`,
+/// or `data/<node id>` in the working directory.
+pub fn node_dir(node_id: ids::NodeId) -> io::Result<PathBuf> {
+    let root =
+        std::env::var_os("NODE_DATA_DIR").map_or_else(|| PathBuf::from("data"), PathBuf::from);
+    let dir = root.join(node_id.to_string());
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Append-only write-ahead log of entries of type T.
+pub struct Wal<T> {
+    path: PathBuf,
+    file: fs::File,
+    policy: FsyncPolicy,
+    unsynced: usize,
+    /// Bytes of complete entries in the file.
+    len: u64,
+    _entries: PhantomData<T>,
+}
+
+impl<T: Serialize + DeserializeOwned> Wal<T> {
+    /// Opens the log at path, creating it if needed, and returns it together
+    /// with the recovered entries.
+    pub fn open(path: impl AsRef<Path>, policy: FsyncPolicy) -> io::Result<(Self, Vec<T>)> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+
+        let mut entries = Vec::new();
+        let mut valid = 0;
+        let mut reader = io::BufReader::new(&file);
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            // entry is only complete with its newline
+            let Some(raw) = line.strip_suffix('\n') else {
+                break;
+            };
+            let entry = match serde_json::from_str(raw) {
+                Ok(entry) => entry,
+                // only the last line can be torn
+                Err(_) if reader.fill_buf()?.is_empty() => break,
+                Err(error) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("corrupt log entry at byte {valid}: {error}"),
+                    ))
+                }
+            };
+            entries.push(entry);
+            valid += line.len() as u64;
+            line.clear();
+        }
+
+        // drop the torn tail, so new entries are not appended after it
+        if valid < file.metadata()?.len() {
+            log::warn!("dropping torn tail of the log after {valid} bytes");
+            file.set_len(valid)?;
+            file.sync_all()?;
+        }
+        file.seek(io::SeekFrom::End(0))?;
+
+        let wal = Self {
+            path,
+            file,
+            policy,
+            unsynced: 0,
+            len: valid,
+            _entries: PhantomData,
+        };
+        Ok((wal, entries))
+    }
+
+    pub fn append(&mut self, entry: &T) -> io::Result<()> {
+        self.append_deferred(entry)?.now()
+    }
+
+    /// Appends the entry, but leaves the flush the policy requires to the
+    /// caller, so it does not have to hold the log while waiting for it.
+    pub fn append_deferred(&mut self, entry: &T) -> io::Result<PendingSync> {
+        let mut raw = serde_json::to_vec(entry)?;
+        raw.push(b'\n');
+        // single write, so the entry is either complete or a torn tail
+        self.file.write_all(&raw)?;
+        self.len += raw.len() as u64;
+
+        self.unsynced += 1;
+        let sync = match self.policy {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::Batch(n) => self.unsynced >= n,
+            FsyncPolicy::Never => false,
+        };
+        if !sync {
+            return Ok(PendingSync(None));
+        }
+        self.unsynced = 0;
+        Ok(PendingSync(Some(self.file.try_clone()?)))
+    }
+
+    /// Flushes appended entries to disk regardless of the policy.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    /// Replaces all entries of the log with the given ones.
+    pub fn rewrite<'a>(&mut self, entries: impl IntoIterator<Item = &'a T>) -> io::Result<()>
+    where
+        T: 'a,
+    {
+        write_atomically(&self.path, |writer| {
+            for entry in entries {
+                serde_json::to_writer(&mut *writer, entry)?;
+                writer.write_all(b"\n")?;
+            }
+            Ok(())
+        })?;
+
+        self.file = fs::OpenOptions::new().append(true).open(&self.path)?;
+        self.len = self.file.metadata()?.len();
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    /// Drops the first len bytes of entries, and keeps the ones after them.
+    fn drop_front(&mut self, len: u64) -> io::Result<()> {
+        let mut old = fs::File::open(&self.path)?;
+        old.seek(io::SeekFrom::Start(len))?;
+        write_atomically(&self.path, |writer| io::copy(&mut old, writer).map(drop))?;
+
+        self.file = fs::OpenOptions::new().append(true).open(&self.path)?;
+        self.len -= len;
+        self.unsynced = 0;
+        Ok(())
+    }
+}
+
+/// Flush of appended entries, which the [FsyncPolicy] requires before they
+/// are acknowledged.
+#[must_use = "entries may not be on disk until synced"]
+pub struct PendingSync(Option<fs::File>);
+
+impl PendingSync {
+    /// Flushes on the blocking thread pool, so the runtime keeps serving
+    /// other tasks meanwhile.
+    pub async fn wait(self) -> io::Result<()> {
+        let Some(file) = self.0 else {
+            return Ok(());
+        };
+        tokio::task::spawn_blocking(move || file.sync_data())
+            .await
+            .expect("sync task panicked")
+    }
+
+    fn now(self) -> io::Result<()> {
+        self.0.map_or(Ok(()), |file| file.sync_data())
+    }
+}
+
+/// Writes the file next to path and renames it over path, so a crash leaves
+/// either the old or the new file.
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut io::BufWriter<fs::File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut writer = io::BufWriter::new(fs::File::create(&tmp)?);
+    write(&mut writer)?;
+    let file = writer.into_inner().map_err(|error| error.into_error())?;
+    file.sync_all()?;
+
+    fs::rename(&tmp, path)?;
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        // makes the rename itself durable
+        fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Append-only log of typed entries, addressed by offset.
+pub struct Log<T> {
+    wal: Wal<T>,
+    entries: Vec<T>,
+}
+
+impl<T: Serialize + DeserializeOwned> Log<T> {
+    pub fn open(path: impl AsRef<Path>, policy: FsyncPolicy) -> io::Result<Self> {
+        let (wal, entries) = Wal::open(path, policy)?;
+        Ok(Self { wal, entries })
+    }
+
+    /// Appends the entry and returns its offset.
+    pub fn append(&mut self, entry: T) -> io::Result<u64> {
+        self.wal.append(&entry)?;
+        self.entries.push(entry);
+        Ok(self.entries.len() as u64 - 1)
+    }
+
+    pub fn get(&self, offset: u64) -> Option<&T> {
+        self.entries.get(usize::try_from(offset).ok()?)
+    }
+
+    /// Returns entries starting from offset.
+    pub fn since(&self, offset: u64) -> &[T] {
+        let start = usize::try_from(offset)
+            .map_or(self.entries.len(), |start| start.min(self.entries.len()));
+        &self.entries[start..]
+    }
+
+    pub fn len(&self) -> u64 {
+        self.entries.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.wal.sync()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum MapEntry<K, V> {
+    Insert { key: K, value: V },
+    Remove { key: K },
+}
+
+/// Key-value map, which logs every change.
+///
+/// The log keeps the whole history of changes, [Map::compact] rewrites it to
+/// the current content.
+pub struct Map<K, V> {
+    wal: Wal<MapEntry<K, V>>,
+    map: HashMap<K, V>,
+}
+
+impl<K, V> Map<K, V>
+where
+    K: Serialize + DeserializeOwned + Eq + Hash + Clone,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    pub fn open(path: impl AsRef<Path>, policy: FsyncPolicy) -> io::Result<Self> {
+        let (wal, entries) = Wal::open(path, policy)?;
+        let mut map = HashMap::new();
+        for entry in entries {
+            match entry {
+                MapEntry::Insert { key, value } => {
+                    map.insert(key, value);
+                }
+                MapEntry::Remove { key } => {
+                    map.remove(&key);
+                }
+            }
+        }
+        Ok(Self { wal, map })
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.map.get(key)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> io::Result<Option<V>> {
+        self.wal.append(&MapEntry::Insert {
+            key: key.clone(),
+            value: value.clone(),
+        })?;
+        Ok(self.map.insert(key, value))
+    }
+
+    pub fn remove(&mut self, key: &K) -> io::Result<Option<V>> {
+        if !self.map.contains_key(key) {
+            return Ok(None);
+        }
+        self.wal.append(&MapEntry::Remove { key: key.clone() })?;
+        Ok(self.map.remove(key))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.map.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Rewrites the log to a single insert per key.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let entries = self
+            .map
+            .iter()
+            .map(|(key, value)| MapEntry::Insert {
+                key: key.clone(),
+                value: value.clone(),
+            })
+            .collect::<Vec<_>>();
+        self.wal.rewrite(&entries)
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.wal.sync()
+    }
+}
+
+/// State that is persisted as a snapshot plus a log of changes made since.
+pub trait Snapshot: Default {
+    /// Change of the state, which is logged before it is applied.
+    type Entry: Serialize + DeserializeOwned;
+
+    fn apply(&mut self, entry: Self::Entry);
+
+    fn snapshot(&self, writer: &mut dyn Write) -> io::Result<()>;
+
+    fn restore(reader: &mut dyn io::Read) -> io::Result<Self>;
+}
+
+#[derive(Serialize, Deserialize)]
+struct Logged<E> {
+    /// Number of entries applied before this one, since the state was created.
+    seq: u64,
+    entry: E,
+}
+
+/// Header of the snapshot file, the state itself follows on the next line.
+#[derive(Serialize, Deserialize)]
+struct SnapshotHeader {
+    /// Number of entries included in the snapshot.
+    seq: u64,
+}
+
+/// State, which survives restarts if it is stored on disk.
+///
+/// Every change is logged to a [Wal] in the directory. [Persistent::snapshot]
+/// writes the whole state and truncates the log, recovery loads the snapshot
+/// and replays the entries logged after it.
+pub struct Persistent<S: Snapshot> {
+    state: S,
+    seq: u64,
+    disk: Option<Disk<S::Entry>>,
+}
+
+struct Disk<E> {
+    dir: PathBuf,
+    wal: Wal<Logged<E>>,
+    /// Entries logged since the last snapshot.
+    unsnapshotted: usize,
+}
+
+impl<S: Snapshot> Persistent<S> {
+    /// State that is not stored anywhere.
+    pub fn in_memory() -> Self {
+        Self {
+            state: S::default(),
+            seq: 0,
+            disk: None,
+        }
+    }
+
+    /// Opens or creates the state stored in dir.
+    pub fn open(dir: impl AsRef<Path>, policy: FsyncPolicy) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let (state, seq) = match fs::File::open(dir.join("snapshot")) {
+            Ok(file) => {
+                let mut reader = io::BufReader::new(file);
+                let mut header = String::new();
+                reader.read_line(&mut header)?;
+                let header = serde_json::from_str::<SnapshotHeader>(&header)?;
+                (S::restore(&mut reader)?, header.seq)
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (S::default(), 0),
+            Err(error) => return Err(error),
+        };
+
+        let (wal, entries) = Wal::<Logged<S::Entry>>::open(dir.join("wal"), policy)?;
+        let mut persistent = Self {
+            state,
+            seq,
+            disk: None,
+        };
+        let mut unsnapshotted = 0;
+        for logged in entries {
+            // entries may have been included in the snapshot, if the node
+            // crashed before truncating the log
+            if logged.seq >= persistent.seq {
+                persistent.state.apply(logged.entry);
+                persistent.seq = logged.seq + 1;
+                unsnapshotted += 1;
+            }
+        }
+        persistent.disk = Some(Disk {
+            dir,
+            wal,
+            unsnapshotted,
+        });
+        Ok(persistent)
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Logs the entry and applies it to the state.
+    ///
+    /// Blocks until the entry is flushed, if the policy requires it.
+    pub fn apply(&mut self, entry: S::Entry) -> io::Result<()> {
+        self.apply_deferred(entry)?.now()
+    }
+
+    /// Logs the entry and applies it to the state, but leaves the flush to
+    /// the caller, see [Wal::append_deferred].
+    ///
+    /// The entry is visible to readers of the state before it is flushed.
+    pub fn apply_deferred(&mut self, entry: S::Entry) -> io::Result<PendingSync> {
+        let logged = Logged {
+            seq: self.seq,
+            entry,
+        };
+        let mut synced = PendingSync(None);
+        if let Some(disk) = &mut self.disk {
+            synced = disk.wal.append_deferred(&logged)?;
+            disk.unsnapshotted += 1;
+        }
+        self.state.apply(logged.entry);
+        self.seq += 1;
+        Ok(synced)
+    }
+
+    /// Writes a snapshot of the state and drops the log entries it includes.
+    pub fn snapshot(&mut self) -> io::Result<()> {
+        let Some(disk) = &mut self.disk else {
+            return Ok(());
+        };
+        if disk.unsnapshotted == 0 {
+            return Ok(());
+        }
+
+        write_snapshot(&disk.dir, self.seq, &self.state)?;
+        disk.wal.rewrite([])?;
+        disk.unsnapshotted = 0;
+        Ok(())
+    }
+}
+
+fn write_snapshot<S: Snapshot>(dir: &Path, seq: u64, state: &S) -> io::Result<()> {
+    let header = SnapshotHeader { seq };
+    write_atomically(&dir.join("snapshot"), |writer| {
+        serde_json::to_writer(&mut *writer, &header)?;
+        writer.write_all(b"\n")?;
+        state.snapshot(writer)
+    })
+}
+
+/// Snapshots the state every interval, so its log does not grow without bound.
+pub async fn snapshot_periodically<S>(persistent: Arc<RwLock<Persistent<S>>>, interval: Duration)
+where
+    S: Snapshot + Clone + Send + Sync + 'static,
+    S::Entry: Send + 'static,
+{
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if let Err(error) = snapshot_concurrently(&persistent).await {
+            log::error!("failed to write snapshot: {error}");
+        }
+    }
+}
+
+/// Writes the snapshot from a copy of the state, taken under the read lock.
+/// Only dropping the snapshotted entries from the log takes the write lock,
+/// entries applied in the meantime stay in the log.
+async fn snapshot_concurrently<S>(persistent: &RwLock<Persistent<S>>) -> io::Result<()>
+where
+    S: Snapshot + Clone + Send + Sync + 'static,
+    S::Entry: Send + 'static,
+{
+    let (state, seq, dir, snapshotted_len, snapshotted) = {
+        let persistent = persistent.read().await;
+        let Some(disk) = &persistent.disk else {
+            return Ok(());
+        };
+        if disk.unsnapshotted == 0 {
+            return Ok(());
+        }
+        (
+            persistent.state.clone(),
+            persistent.seq,
+            disk.dir.clone(),
+            disk.wal.len,
+            disk.unsnapshotted,
+        )
+    };
+    tokio::task::spawn_blocking(move || write_snapshot(&dir, seq, &state))
+        .await
+        .expect("snapshot task panicked")?;
+
+    let mut persistent = persistent.write().await;
+    let Some(mut disk) = persistent.disk.take() else {
+        return Ok(());
+    };
+    let (mut disk, dropped) = tokio::task::spawn_blocking(move || {
+        let dropped = disk.wal.drop_front(snapshotted_len);
+        (disk, dropped)
+    })
+    .await
+    .expect("snapshot task panicked");
+    if dropped.is_ok() {
+        disk.unsnapshotted -= snapshotted;
+    }
+    persistent.disk = Some(disk);
+    dropped
+}
+
+#[test]
+fn test_log_recovery() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("log");
+
+    let mut log = Log::open(&path, FsyncPolicy::Batch(2)).unwrap();
+    assert_eq!(log.append("a".to_string()).unwrap(), 0);
+    assert_eq!(log.append("b".to_string()).unwrap(), 1);
+    drop(log);
+
+    let mut log = Log::<String>::open(&path, FsyncPolicy::Always).unwrap();
+    assert_eq!(log.since(0), ["a", "b"]);
+    assert_eq!(log.append("c".to_string()).unwrap(), 2);
+    assert_eq!(log.get(2).unwrap(), "c");
+    assert_eq!(log.since(5), [] as [String; 0]);
+}
+
+#[test]
+fn test_torn_tail() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("log");
+
+    let mut log = Log::open(&path, FsyncPolicy::Never).unwrap();
+    log.append(1).unwrap();
+    log.append(2).unwrap();
+    drop(log);
+    // crash in the middle of the third append
+    fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(b"3")
+        .unwrap();
+
+    let mut log = Log::<u32>::open(&path, FsyncPolicy::Never).unwrap();
+    assert_eq!(log.since(0), [1, 2]);
+    log.append(4).unwrap();
+    drop(log);
+
+    let log = Log::<u32>::open(&path, FsyncPolicy::Never).unwrap();
+    assert_eq!(log.since(0), [1, 2, 4]);
+}
+
+#[test]
+fn test_corrupt_entry() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("log");
+
+    // unreadable last line is a torn tail, even with its newline
+    fs::write(&path, "1\n2\nx\n").unwrap();
+    let log = Log::<u32>::open(&path, FsyncPolicy::Never).unwrap();
+    assert_eq!(log.since(0), [1, 2]);
+    drop(log);
+
+    fs::write(&path, "1\nx\n3\n").unwrap();
+    let error = Log::<u32>::open(&path, FsyncPolicy::Never).err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "1\nx\n3\n");
+}
+
+#[test]
+fn test_drop_front() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("log");
+
+    let (mut wal, _) = Wal::<u32>::open(&path, FsyncPolicy::Never).unwrap();
+    wal.append(&1).unwrap();
+    wal.append(&2).unwrap();
+    let snapshotted = wal.len;
+    // appended while the snapshot is written
+    wal.append(&3).unwrap();
+    wal.drop_front(snapshotted).unwrap();
+    wal.append(&4).unwrap();
+    drop(wal);
+
+    let (_, entries) = Wal::<u32>::open(&path, FsyncPolicy::Never).unwrap();
+    assert_eq!(entries, [3, 4]);
+}
+
+#[test]
+fn test_map_recovery() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("map");
+
+    let mut map = Map::open(&path, FsyncPolicy::Always).unwrap();
+    map.insert("a".to_string(), 1).unwrap();
+    map.insert("b".to_string(), 2).unwrap();
+    assert_eq!(map.insert("a".to_string(), 3).unwrap(), Some(1));
+    map.remove(&"b".to_string()).unwrap();
+    drop(map);
+
+    let mut map = Map::<String, u32>::open(&path, FsyncPolicy::Always).unwrap();
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.get(&"a".to_string()), Some(&3));
+    map.compact().unwrap();
+    drop(map);
+
+    assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+    let map = Map::<String, u32>::open(&path, FsyncPolicy::Always).unwrap();
+    assert_eq!(map.get(&"a".to_string()), Some(&3));
+}
+
+#[cfg(test)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Sum(u64);
+
+#[cfg(test)]
+impl Snapshot for Sum {
+    type Entry = u64;
+
+    fn apply(&mut self, entry: u64) {
+        self.0 += entry;
+    }
+
+    fn snapshot(&self, writer: &mut dyn Write) -> io::Result<()> {
+        Ok(serde_json::to_writer(writer, self)?)
+    }
+
+    fn restore(reader: &mut dyn io::Read) -> io::Result<Self> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+#[test]
+fn test_snapshot_recovery() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut sum = Persistent::<Sum>::open(dir.path(), FsyncPolicy::Never).unwrap();
+    sum.apply(1).unwrap();
+    sum.apply(2).unwrap();
+    sum.snapshot().unwrap();
+    sum.apply(3).unwrap();
+    drop(sum);
+
+    assert_eq!(
+        fs::read_to_string(dir.path().join("wal"))
+            .unwrap()
+            .lines()
+            .count(),
+        1
+    );
+    let mut sum = Persistent::<Sum>::open(dir.path(), FsyncPolicy::Never).unwrap();
+    assert_eq!(sum.state(), &Sum(6));
+
+    // crash after writing the snapshot, but before truncating the log
+    let wal = fs::read(dir.path().join("wal")).unwrap();
+    sum.snapshot().unwrap();
+    fs::write(dir.path().join("wal"), wal).unwrap();
+    drop(sum);
+
+    let sum = Persistent::<Sum>::open(dir.path(), FsyncPolicy::Never).unwrap();
+    assert_eq!(sum.state(), &Sum(6));
+}
+
+#[test]
+fn test_concurrent_snapshot() {
+    let dir = tempfile::tempdir().unwrap();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    let mut sum = Persistent::<Sum>::open(dir.path(), FsyncPolicy::Always).unwrap();
+    sum.apply(1).unwrap();
+    sum.apply(2).unwrap();
+    let sum = RwLock::new(sum);
+    runtime.block_on(async {
+        let synced = sum.write().await.apply_deferred(3).unwrap();
+        synced.wait().await.unwrap();
+        snapshot_concurrently(&sum).await.unwrap();
+        sum.write().await.apply(4).unwrap();
+    });
+    drop(sum);
+
+    assert_eq!(
+        fs::read_to_string(dir.path().join("wal"))
+            .unwrap()
+            .lines()
+            .count(),
+        1
+    );
+    let sum = Persistent::<Sum>::open(dir.path(), FsyncPolicy::Never).unwrap();
+    assert_eq!(sum.state(), &Sum(10));
+}
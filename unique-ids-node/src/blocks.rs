@@ -0,0 +1,139 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use kv::{KeyValueStore, KvError};
+use maelstrom_node::{ids, ErrorCode, ErrorResponse};
+use tokio::sync::Mutex;
+
+use crate::generator::Generator;
+
+/// Key of the first id that has not been leased to any node yet.
+const HIGH_WATER_KEY: &str = "ids_high_water";
+
+/// Hands out ids from blocks leased from a high-water mark in the store.
+///
+/// The high-water mark only grows and is persisted before any id of the block
+/// is issued, so a restarted node leases a new block instead of reissuing ids
+/// from the old one. Ids left in the block of a stopped node are never issued.
+#[derive(Clone)]
+pub struct Blocks<S> {
+    store: S,
+    size: u64,
+    block: Arc<Mutex<Range<u64>>>,
+}
+
+impl<S: KeyValueStore> Blocks<S> {
+    pub fn new(store: S, size: u64) -> Self {
+        assert!(size > 0, "block size must be positive");
+        Self {
+            store,
+            size,
+            block: Arc::default(),
+        }
+    }
+
+    /// Leases count consecutive blocks with a single update.
+    async fn lease(&self, count: u64) -> Result<Range<u64>, ErrorResponse> {
+        let size = self.size * count;
+        let high_water = self
+            .store
+            .update(HIGH_WATER_KEY, 0u64, |high_water| high_water + size)
+            .await
+            .map_err(|error: KvError| ErrorResponse {
+                code: ErrorCode::TemporarilyUnavailable,
+                text: format!("failed to lease a block of ids: {error}"),
+            })?;
+        Ok(high_water - size..high_water)
+    }
+}
+
+impl<S: KeyValueStore> Generator for Blocks<S> {
+    type Id = u64;
+
+    async fn generate(&self, _node_id: ids::NodeId) -> Result<u64, ErrorResponse> {
+        // held during the lease, so the node leases one block at a time
+        let mut block = self.block.lock().await;
+        if block.is_empty() {
+            *block = self.lease(1).await?;
+        }
+        Ok(block.next().expect("leased block is empty"))
+    }
+
+    /// Takes the rest of the current block and leases all blocks the batch
+    /// needs beyond it at once, holding the block the whole time, so no other
+    /// id is issued in between.
+    async fn generate_batch(
+        &self,
+        _node_id: ids::NodeId,
+        count: usize,
+    ) -> Result<Vec<u64>, ErrorResponse> {
+        let mut block = self.block.lock().await;
+        let count = count as u64;
+        let needed = count.saturating_sub(block.end - block.start);
+        if needed > 0 {
+            let mut leased = self.lease(needed.div_ceil(self.size)).await?;
+            let mut ids = block.by_ref().collect::<Vec<_>>();
+            ids.extend(leased.by_ref().take(needed as usize));
+            *block = leased;
+            return Ok(ids);
+        }
+        Ok(block.by_ref().take(count as usize).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kv::{Consistency, MemoryKV};
+
+    use super::*;
+
+    async fn generate(blocks: &Blocks<MemoryKV>, count: usize) -> Vec<u64> {
+        let mut ids = Vec::new();
+        for _ in 0..count {
+            ids.push(blocks.generate(0.into()).await.unwrap());
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn nodes_lease_distinct_blocks() {
+        let store = MemoryKV::new(Consistency::Linearizable);
+        let a = Blocks::new(store.client(), 3);
+        let b = Blocks::new(store.client(), 3);
+
+        let mut ids = generate(&a, 4).await;
+        ids.extend(generate(&b, 4).await);
+        ids.extend(generate(&a, 4).await);
+
+        assert_eq!(ids, [0, 1, 2, 3, 6, 7, 8, 9, 4, 5, 12, 13]);
+    }
+
+    #[tokio::test]
+    async fn batch_leases_blocks_at_once() {
+        let store = MemoryKV::new(Consistency::Linearizable);
+        let a = Blocks::new(store.client(), 3);
+        let b = Blocks::new(store.client(), 3);
+
+        assert_eq!(generate(&a, 1).await, [0]);
+        assert_eq!(
+            a.generate_batch(0.into(), 6).await.unwrap(),
+            [1, 2, 3, 4, 5, 6]
+        );
+        // two blocks were leased with one update
+        assert_eq!(store.read::<u64>(HIGH_WATER_KEY).await.unwrap(), 9);
+        assert_eq!(generate(&b, 1).await, [9]);
+
+        assert_eq!(generate(&a, 2).await, [7, 8]);
+        assert!(a.generate_batch(0.into(), 0).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn restart_does_not_reissue() {
+        let store = MemoryKV::new(Consistency::Linearizable);
+        let before = generate(&Blocks::new(store.client(), 10), 2).await;
+        let after = generate(&Blocks::new(store.client(), 10), 2).await;
+
+        assert_eq!(before, [0, 1]);
+        assert_eq!(after, [10, 11]);
+    }
+}
@@ -0,0 +1,122 @@
+use std::future::Future;
+use std::sync::{atomic, Arc, Mutex};
+
+use maelstrom_node::{ids, ErrorCode, ErrorResponse};
+use serde::Serialize;
+
+/// Source of unique ids, shared by all requests to the node.
+pub trait Generator: Clone + Send + Sync + 'static {
+    type Id: Serialize + Send;
+
+    fn generate(
+        &self,
+        node_id: ids::NodeId,
+    ) -> impl Future<Output = Result<Self::Id, ErrorResponse>> + Send;
+
+    /// Generates count ids in increasing order. Generators, which can reserve
+    /// the whole range at once, should do so.
+    fn generate_batch(
+        &self,
+        node_id: ids::NodeId,
+        count: usize,
+    ) -> impl Future<Output = Result<Vec<Self::Id>, ErrorResponse>> + Send {
+        let generator = self.clone();
+        async move {
+            let mut ids = Vec::with_capacity(count);
+            for _ in 0..count {
+                ids.push(generator.generate(node_id).await?);
+            }
+            Ok(ids)
+        }
+    }
+}
+
+/// Per-node counter, prefixed with the node id.
+#[derive(Default, Clone)]
+pub struct Counter {
+    ids_counter: Arc<atomic::AtomicU64>,
+}
+
+impl Generator for Counter {
+    type Id = u64;
+
+    async fn generate(&self, node_id: ids::NodeId) -> Result<u64, ErrorResponse> {
+        let counter = self.ids_counter.fetch_add(1, atomic::Ordering::SeqCst);
+
+        // This gives us 2^32 unique ids for every of 2^32 nodes.
+        Ok(u64::from(node_id) << 32 | counter)
+    }
+
+    async fn generate_batch(
+        &self,
+        node_id: ids::NodeId,
+        count: usize,
+    ) -> Result<Vec<u64>, ErrorResponse> {
+        let first = self
+            .ids_counter
+            .fetch_add(count as u64, atomic::Ordering::SeqCst);
+
+        let prefix = u64::from(node_id) << 32;
+        Ok((first..first + count as u64)
+            .map(|counter| prefix | counter)
+            .collect())
+    }
+}
+
+/// Random UUIDv7 with a millisecond timestamp prefix.
+#[derive(Default, Clone)]
+pub struct UuidV7;
+
+impl Generator for UuidV7 {
+    type Id = String;
+
+    async fn generate(&self, _node_id: ids::NodeId) -> Result<String, ErrorResponse> {
+        Ok(uuid::Uuid::now_v7().to_string())
+    }
+}
+
+/// ULIDs, monotonic within the node.
+#[derive(Default, Clone)]
+pub struct Ulid {
+    generator: Arc<Mutex<ulid::Generator>>,
+}
+
+impl Generator for Ulid {
+    type Id = String;
+
+    async fn generate(&self, _node_id: ids::NodeId) -> Result<String, ErrorResponse> {
+        let ulid = self.generator.lock().expect("Lock poisoned").generate();
+        ulid.map(|ulid| ulid.to_string())
+            .map_err(|error| ErrorResponse {
+                code: ErrorCode::TemporarilyUnavailable,
+                text: error.to_string(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn counter_batch_is_contiguous() {
+        let counter = Counter::default();
+        let node_id = ids::NodeId::from(1);
+        let prefix = 1 << 32;
+
+        assert_eq!(counter.generate(node_id).await.unwrap(), prefix);
+        assert_eq!(
+            counter.generate_batch(node_id, 3).await.unwrap(),
+            [prefix + 1, prefix + 2, prefix + 3]
+        );
+        assert_eq!(counter.generate(node_id).await.unwrap(), prefix + 4);
+    }
+
+    #[tokio::test]
+    async fn ulid_batch_is_sorted() {
+        let ids = Ulid::default().generate_batch(0.into(), 100).await.unwrap();
+
+        assert_eq!(ids.len(), 100);
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
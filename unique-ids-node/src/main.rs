@@ -1,35 +1,58 @@
-use std::sync::atomic;
-use std::sync::Arc;
+mod blocks;
+mod generator;
+mod snowflake;
 
 use serde::Deserialize;
 use serde_json::json;
 use tokio::{spawn, sync};
 
-use maelstrom_node::{protocol, read_from_stdin, write_to_stdout, Handler, Node};
+use maelstrom_node::{
+    protocol, read_from_stdin, write_to_stdout, ErrorCode, ErrorResponse, Handler, Node,
+};
 
-#[derive(Default, Clone)]
-struct UniqueIdsHandler {
-    ids_counter: Arc<atomic::AtomicU64>,
+use generator::Generator;
+
+/// Largest number of ids a single `generate_batch` may ask for.
+const MAX_BATCH: usize = 10_000;
+
+#[derive(Clone)]
+struct UniqueIdsHandler<G> {
+    generator: G,
 }
 
 #[derive(Deserialize)]
-#[serde[tag = "generate", rename = "type"]]
-struct GenerateRequest {}
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    Generate,
+    GenerateBatch { count: usize },
+}
 
-impl Handler for UniqueIdsHandler {
+impl<G: Generator> Handler for UniqueIdsHandler<G> {
     async fn handle(&self, node: maelstrom_node::Node, message: maelstrom_node::protocol::Message) {
-        let Ok(_) = message.clone_into::<protocol::Request<GenerateRequest>>() else {
+        let Ok(request) = message.clone_into::<protocol::Request<Request>>() else {
             return;
         };
 
-        let counter = self.ids_counter.fetch_add(1, atomic::Ordering::SeqCst);
-
-        // This gives us 2^32 unique ids for every of 2^32 nodes.
-        let id = u64::from(node.id) << 32 | counter;
-
-        node.reply(&message, json!({"id": id}))
-            .await
-            .expect("failed to reply")
+        match request.payload {
+            Request::Generate => match self.generator.generate(node.id).await {
+                Ok(id) => node.reply(&message, json!({"id": id})).await,
+                Err(error) => node.reply(&message, error).await,
+            },
+            Request::GenerateBatch { count } if count > MAX_BATCH => {
+                let error = ErrorResponse {
+                    code: ErrorCode::MalformedRequest,
+                    text: format!("count must not exceed {MAX_BATCH}"),
+                };
+                node.reply(&message, error).await
+            }
+            Request::GenerateBatch { count } => {
+                match self.generator.generate_batch(node.id, count).await {
+                    Ok(ids) => node.reply(&message, json!({"ids": ids})).await,
+                    Err(error) => node.reply(&message, error).await,
+                }
+            }
+        }
+        .expect("failed to reply")
     }
 }
 
@@ -41,8 +64,42 @@ async fn main() {
     let handle = spawn(write_to_stdout(responses_rx));
 
     let node = Node::initialize(&mut requests_rx, responses_tx.clone()).await;
-    node.listen(&mut requests_rx, UniqueIdsHandler::default())
-        .await;
+
+    // Maelstrom starts nodes without arguments, so modes come from environment
+    match std::env::var("UNIQUE_IDS_MODE").as_deref() {
+        // Blocks of ids leased from lin-kv, survive restarts
+        Ok("blocks") => {
+            let size = std::env::var("ID_BLOCK_SIZE")
+                .ok()
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(1000);
+            let generator = blocks::Blocks::new(kv::KV::new_lin(node.clone()), size);
+            node.listen(&mut requests_rx, UniqueIdsHandler { generator })
+                .await;
+        }
+        // Roughly time-ordered ids
+        Ok("snowflake") => {
+            let generator =
+                snowflake::Snowflake::new(node.id).expect("failed to start snowflake ids");
+            node.listen(&mut requests_rx, UniqueIdsHandler { generator })
+                .await;
+        }
+        Ok("uuidv7") => {
+            let generator = generator::UuidV7;
+            node.listen(&mut requests_rx, UniqueIdsHandler { generator })
+                .await;
+        }
+        Ok("ulid") => {
+            let generator = generator::Ulid::default();
+            node.listen(&mut requests_rx, UniqueIdsHandler { generator })
+                .await;
+        }
+        _ => {
+            let generator = generator::Counter::default();
+            node.listen(&mut requests_rx, UniqueIdsHandler { generator })
+                .await;
+        }
+    }
 
     handle.await.expect("Task panic");
 }
@@ -0,0 +1,165 @@
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use maelstrom_node::{ids, ErrorResponse};
+
+use crate::generator::Generator;
+
+const NODE_BITS: u32 = 10;
+const SEQUENCE_BITS: u32 = 12;
+const MAX_SEQUENCE: u64 = (1 << SEQUENCE_BITS) - 1;
+
+/// Milliseconds since unix epoch of 2024-01-01, leaves 41 bits of timestamp
+/// for about 69 years.
+const EPOCH_MS: u64 = 1_704_067_200_000;
+
+/// Snowflake ids: 41 bits of milliseconds since [EPOCH_MS], 10 bits of node id
+/// and 12 bits of sequence within the millisecond.
+///
+/// Timestamps never go back: if the clock regresses, or the sequence runs out,
+/// ids are issued ahead of the clock until it catches up.
+#[derive(Clone)]
+pub struct Snowflake {
+    node_id: ids::NodeId,
+    state: Arc<Mutex<State>>,
+}
+
+/// Node id, which does not fit in [NODE_BITS].
+#[derive(Debug, PartialEq)]
+pub struct NodeIdOutOfRange(pub ids::NodeId);
+
+impl std::fmt::Display for NodeIdOutOfRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "node id {} does not fit in {NODE_BITS} bits of snowflake ids",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for NodeIdOutOfRange {}
+
+impl Snowflake {
+    /// Fails for node ids, which would collide with ids of another node.
+    pub fn new(node_id: ids::NodeId) -> Result<Self, NodeIdOutOfRange> {
+        if u64::from(node_id) >= 1 << NODE_BITS {
+            return Err(NodeIdOutOfRange(node_id));
+        }
+        Ok(Self {
+            node_id,
+            state: Arc::default(),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Timestamp of the last issued id.
+    last_ms: u64,
+    sequence: u64,
+}
+
+impl State {
+    fn next(&mut self, now_ms: u64, node_id: ids::NodeId) -> u64 {
+        if now_ms > self.last_ms {
+            self.last_ms = now_ms;
+            self.sequence = 0;
+        } else if self.sequence < MAX_SEQUENCE {
+            self.sequence += 1;
+        } else {
+            self.last_ms += 1;
+            self.sequence = 0;
+        }
+
+        (self.last_ms - EPOCH_MS) << (NODE_BITS + SEQUENCE_BITS)
+            | u64::from(node_id) << SEQUENCE_BITS
+            | self.sequence
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("clock before unix epoch")
+        .as_millis() as u64
+}
+
+impl Generator for Snowflake {
+    type Id = u64;
+
+    async fn generate(&self, _node_id: ids::NodeId) -> Result<u64, ErrorResponse> {
+        let mut state = self.state.lock().expect("Lock poisoned");
+        Ok(state.next(now_ms().max(EPOCH_MS), self.node_id))
+    }
+
+    /// Issues the whole batch under the lock, so its sequence range is not
+    /// interleaved with other ids.
+    async fn generate_batch(
+        &self,
+        _node_id: ids::NodeId,
+        count: usize,
+    ) -> Result<Vec<u64>, ErrorResponse> {
+        let mut state = self.state.lock().expect("Lock poisoned");
+        let now_ms = now_ms().max(EPOCH_MS);
+        Ok((0..count)
+            .map(|_| state.next(now_ms, self.node_id))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = EPOCH_MS + 1000;
+
+    fn parts(id: u64) -> (u64, u64, u64) {
+        (
+            (id >> (NODE_BITS + SEQUENCE_BITS)) + EPOCH_MS,
+            id >> SEQUENCE_BITS & ((1 << NODE_BITS) - 1),
+            id & MAX_SEQUENCE,
+        )
+    }
+
+    #[test]
+    fn layout() {
+        let mut state = State::default();
+        assert_eq!(parts(state.next(NOW, 5.into())), (NOW, 5, 0));
+        assert_eq!(parts(state.next(NOW, 5.into())), (NOW, 5, 1));
+        assert_eq!(parts(state.next(NOW + 1, 5.into())), (NOW + 1, 5, 0));
+    }
+
+    #[test]
+    fn rejects_node_ids_out_of_range() {
+        let last = ids::NodeId::from((1 << NODE_BITS) - 1);
+        assert!(Snowflake::new(last).is_ok());
+
+        let first_out = ids::NodeId::from(1 << NODE_BITS);
+        assert_eq!(
+            Snowflake::new(first_out).err(),
+            Some(NodeIdOutOfRange(first_out))
+        );
+    }
+
+    #[test]
+    fn clock_regression() {
+        let mut state = State::default();
+        let first = state.next(NOW, 0.into());
+        let second = state.next(NOW - 500, 0.into());
+
+        assert!(second > first);
+        assert_eq!(parts(second), (NOW, 0, 1));
+    }
+
+    #[test]
+    fn sequence_overflow() {
+        let mut state = State::default();
+        let ids = (0..=MAX_SEQUENCE + 1)
+            .map(|_| state.next(NOW, 0.into()))
+            .collect::<Vec<_>>();
+
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(parts(*ids.last().unwrap()), (NOW + 1, 0, 0));
+    }
+}